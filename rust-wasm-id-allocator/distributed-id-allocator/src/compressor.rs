@@ -1,11 +1,20 @@
+pub(crate) mod capacity_change;
+pub(crate) mod capacity_policy;
+pub(crate) mod finalized_state;
 pub(crate) mod persistence;
 pub(crate) mod persistence_utils;
+pub(crate) mod sequencer;
 pub(crate) mod tables;
-use self::persistence::DeserializationError;
-use self::tables::final_space::FinalSpace;
-use self::tables::session_space::{ClusterRef, SessionSpace, SessionSpaceRef, Sessions};
+pub(crate) mod validation;
+pub use self::capacity_change::CapacityChange;
+pub use self::capacity_policy::{AdaptiveCapacityPolicy, ClusterCapacityPolicy};
+use self::finalized_state::FinalizedState;
+pub use self::persistence::DeserializationError;
+pub use self::sequencer::SequencerCompressor;
+use self::tables::session_space::{IdCluster, SessionSpace, SessionSpaceRef};
 use self::tables::session_space_normalizer::SessionSpaceNormalizer;
-use id_types::final_id::final_id_from_id;
+pub use self::validation::{ValidationIssue, ValidationReport};
+use id_types::final_id::get_id_from_final_id;
 use id_types::local_id::local_id_from_id;
 use id_types::*;
 
@@ -46,15 +55,18 @@ pub struct IdCompressor {
     generated_id_count: u64,
     // The generation count value for the next IdRange_'s range base.
     next_range_base_generation_count: u64,
-    // Cache of one greater than the largest finalized final ID in final space. Used to optimize normalization.
-    final_id_limit: FinalId,
-    // The current value for sizing of new clusters.
-    cluster_capacity: u64,
+    // Ranges taken by take_next_range() that have not yet been finalized, as
+    // (first generation count, count of IDs, capacity hint when taken).
+    pending_ranges: Vec<(u64, u64, Option<u64>)>,
+    // The cluster capacity requested by ranges taken by take_next_range(), if any.
+    capacity_hint: Option<u64>,
+    // The session on whose behalf IDs are allocated while a ghost session is in progress.
+    // It is not added to the session table until IDs are first allocated on its behalf.
+    ghost_session_id: Option<SessionId>,
     // Telemetry data.
     telemetry_stats: TelemetryStats,
     // Data structures:
-    sessions: Sessions,
-    final_space: FinalSpace,
+    finalized: FinalizedState,
     session_space_normalizer: SessionSpaceNormalizer,
 }
 
@@ -64,6 +76,11 @@ impl IdCompressor {
         persistence::DEFAULT_CLUSTER_CAPACITY
     }
 
+    /// Returns the default for the largest cluster capacity that a range may request via its capacity hint.
+    pub fn get_default_max_capacity_hint() -> u64 {
+        persistence::DEFAULT_MAX_CAPACITY_HINT
+    }
+
     #[cfg(feature = "uuid-generation")]
     /// Instantiates a new allocator with a random session ID.
     /// Only available when the "uuid-generation" feature is enabled.
@@ -74,17 +91,17 @@ impl IdCompressor {
 
     /// Instantiates a new allocator with the supplied SessionId.
     pub fn new_with_session_id(session_id: SessionId) -> Self {
-        let mut sessions = Sessions::new();
+        let mut finalized = FinalizedState::new();
         IdCompressor {
             session_id,
-            local_session_ref: sessions.get_or_create(session_id),
+            local_session_ref: finalized.sessions.get_or_create(session_id),
             generated_id_count: 0,
             next_range_base_generation_count: local_id_from_id(-1).to_generation_count(),
-            sessions,
-            final_space: FinalSpace::new(),
-            final_id_limit: final_id_from_id(0),
+            pending_ranges: Vec::new(),
+            capacity_hint: None,
+            finalized,
             session_space_normalizer: SessionSpaceNormalizer::new(),
-            cluster_capacity: persistence::DEFAULT_CLUSTER_CAPACITY,
+            ghost_session_id: None,
             telemetry_stats: TelemetryStats::EMPTY,
         }
     }
@@ -96,7 +113,9 @@ impl IdCompressor {
 
     /// Returns a reference to this compressor's session space.
     fn get_local_session_space(&self) -> &SessionSpace {
-        self.sessions.deref_session_space(self.local_session_ref)
+        self.finalized
+            .sessions
+            .deref_session_space(self.local_session_ref)
     }
 
     /// Returns a token representing the supplied session ID, or an error if no such session has been seen by the compressor.
@@ -111,7 +130,7 @@ impl IdCompressor {
         &self,
         session_id: SessionId,
     ) -> Result<i64, AllocatorError> {
-        match self.sessions.get(session_id) {
+        match self.finalized.sessions.get(session_id) {
             None => Err(AllocatorError::NoTokenForSession),
             Some(session_space) => Ok(session_space.get_index() as i64),
         }
@@ -119,11 +138,12 @@ impl IdCompressor {
 
     /// Returns the current sizing used for new clusters.
     pub fn get_cluster_capacity(&self) -> u64 {
-        self.cluster_capacity
+        self.finalized.cluster_capacity
     }
 
     /// Updates the sizing used for new cluster creation.
     /// This value should only be updated by ordered consensus, or divergence will occur.
+    /// Unlike `IdCompressor::apply_capacity_change`, the change is not recorded in the persisted capacity history.
     ///
     /// > # Errors
     /// > * `AllocatorError::InvalidClusterCapacity`
@@ -133,18 +153,145 @@ impl IdCompressor {
         &mut self,
         new_cluster_capacity: u64,
     ) -> Result<(), AllocatorError> {
-        if new_cluster_capacity < 1 {
-            Err(AllocatorError::InvalidClusterCapacity)
-        } else {
-            self.cluster_capacity = new_cluster_capacity;
-            Ok(())
+        self.finalized.set_cluster_capacity(new_cluster_capacity)
+    }
+
+    /// Returns a [CapacityChange] op updating the sizing used for new cluster creation, for sequencing to all compressors.
+    /// The change does not take effect until it is applied via `apply_capacity_change`.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The supplied cluster size must be a non-zero integer.
+    pub fn propose_capacity_change(
+        &self,
+        new_cluster_capacity: u64,
+    ) -> Result<CapacityChange, AllocatorError> {
+        self.finalized.propose_capacity_change(new_cluster_capacity)
+    }
+
+    /// Applies a sequenced [CapacityChange], recording it in the persisted state of the document.
+    /// Changes must be applied in the same total order as finalized ranges by all compressors.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::CapacityChangeDiverged`
+    /// >   * The change was proposed against a cluster capacity other than the current one.
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The new cluster size must be a non-zero integer.
+    pub fn apply_capacity_change(&mut self, change: &CapacityChange) -> Result<(), AllocatorError> {
+        self.finalized.apply_capacity_change(change)
+    }
+
+    /// Returns the largest cluster capacity that a range may request via its capacity hint.
+    pub fn get_max_capacity_hint(&self) -> u64 {
+        self.finalized.max_capacity_hint
+    }
+
+    /// Updates the largest cluster capacity that a range may request via its capacity hint.
+    /// Larger hints are clamped to this bound when finalized.
+    /// This value should only be updated by ordered consensus, or divergence will occur.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The supplied bound must be a non-zero integer.
+    pub fn set_max_capacity_hint(&mut self, max_capacity_hint: u64) -> Result<(), AllocatorError> {
+        self.finalized.set_max_capacity_hint(max_capacity_hint)
+    }
+
+    /// Returns the exclusive upper bound on the final IDs and generation counts of this compressor.
+    pub fn get_final_id_ceiling(&self) -> u64 {
+        self.finalized.final_id_ceiling
+    }
+
+    /// Updates the exclusive upper bound on the final IDs and generation counts of this compressor.
+    /// Ranges whose finalization would create or expand a cluster beyond the ceiling fail to finalize, and IDs whose
+    /// generation counts would reach it fail to generate. Intended for hosts that cannot represent every `u64` exactly
+    /// (e.g. JavaScript, whose numbers are only exact below 2^53).
+    /// The ceiling is not persisted, and must be configured identically by every compressor or divergence will occur.
+    pub fn set_final_id_ceiling(&mut self, final_id_ceiling: u64) {
+        self.finalized.final_id_ceiling = final_id_ceiling;
+    }
+
+    /// Returns the cluster capacity requested by the ranges this compressor takes, if any.
+    pub fn get_capacity_hint(&self) -> Option<u64> {
+        self.capacity_hint
+    }
+
+    /// Sets the cluster capacity requested by the ranges this compressor takes (see [IdRange]).
+    /// Sessions that allocate many IDs can request larger clusters, and sessions that allocate few can request smaller ones.
+    /// The hint is local to this compressor and is not persisted, though each pending range retains (and persists) the
+    /// hint in effect when it was taken.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The supplied hint must be a non-zero integer.
+    pub fn set_capacity_hint(&mut self, capacity_hint: Option<u64>) -> Result<(), AllocatorError> {
+        if capacity_hint == Some(0) {
+            return Err(AllocatorError::InvalidClusterCapacity);
         }
+        self.capacity_hint = capacity_hint;
+        Ok(())
+    }
+
+    /// Begins a ghost session, during which `generate_next_id` allocates final IDs directly on behalf of the supplied session.
+    /// Ghost sessions allow every compressor to deterministically produce identical IDs without communicating (e.g. during a
+    /// migration), and so must only be begun and ended at the same point in the total order by all compressors.
+    /// No local IDs or outstanding ranges are created while the ghost session is in progress.
+    /// Once IDs have been allocated on behalf of a ghost session, that session's local IDs are in use, so it can never
+    /// finalize ranges of its own (they are rejected as out of order) and cannot be ghosted again.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidGhostSession`
+    /// >   * A ghost session is already in progress, the supplied session is the local session, or the supplied session
+    /// >     already has IDs in the document.
+    pub fn begin_ghost_session(&mut self, session_id: SessionId) -> Result<(), AllocatorError> {
+        if self.ghost_session_id.is_some() || session_id == self.session_id {
+            return Err(AllocatorError::InvalidGhostSession);
+        }
+        if let Some(&session_space_ref) = self.finalized.sessions.get(session_id) {
+            // Ghost allocations would leave no room for ranges from the session itself.
+            if !self
+                .finalized
+                .sessions
+                .deref_session_space(session_space_ref)
+                .cluster_chain_is_empty()
+            {
+                return Err(AllocatorError::InvalidGhostSession);
+            }
+        }
+        self.ghost_session_id = Some(session_id);
+        Ok(())
+    }
+
+    /// Ends the ghost session in progress (if any), returning ID generation to the local session.
+    pub fn end_ghost_session(&mut self) {
+        self.ghost_session_id = None;
     }
 
     /// Generates and returns this compressor's next session space ID.
+    /// If a ghost session is in progress, the returned ID is a final ID allocated on behalf of the ghost session.
+    ///
+    /// # Panics
+    /// If the ID cannot be generated (see `IdCompressor::try_generate_next_id`).
     pub fn generate_next_id(&mut self) -> SessionSpaceId {
+        self.try_generate_next_id().expect("ID must be generated.")
+    }
+
+    /// Generates and returns this compressor's next session space ID, as `IdCompressor::generate_next_id`.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::FinalIdSpaceExhausted`
+    /// >   * The generation count of the ID, or the final ID allocated on behalf of a ghost session, would not be less than
+    /// >     the final ID ceiling of the compressor.
+    /// > * `AllocatorError::ClusterCollision`
+    /// >   * The final ID allocated on behalf of a ghost session would collide with the IDs of another session.
+    pub fn try_generate_next_id(&mut self) -> Result<SessionSpaceId, AllocatorError> {
+        if let Some(ghost_session_id) = self.ghost_session_id {
+            return Ok(self.allocate_ghost_finals(ghost_session_id, 1)?.into());
+        }
+        self.check_generation_ceiling(1)?;
         self.generated_id_count += 1;
         let tail_cluster = match self
+            .finalized
             .sessions
             .deref_session_space(self.local_session_ref)
             .get_tail_cluster()
@@ -152,7 +299,7 @@ impl IdCompressor {
             Some(cluster) => cluster,
             None => {
                 // No cluster, return next local
-                return self.generate_next_local_id().into();
+                return Ok(self.generate_next_local_id().into());
             }
         };
         let cluster_offset =
@@ -160,10 +307,18 @@ impl IdCompressor {
         if tail_cluster.capacity > cluster_offset {
             // Space in the cluster: eager final
             self.telemetry_stats.eager_final_count += 1;
-            (tail_cluster.base_final_id + cluster_offset).into()
+            Ok((tail_cluster.base_final_id + cluster_offset).into())
         } else {
             // No space in the cluster, return next local
-            self.generate_next_local_id().into()
+            Ok(self.generate_next_local_id().into())
+        }
+    }
+
+    // Checks that `count` more IDs can be generated without their generation counts reaching the final ID ceiling.
+    fn check_generation_ceiling(&self, count: u64) -> Result<(), AllocatorError> {
+        match self.generated_id_count.checked_add(count) {
+            Some(generation_count) if generation_count < self.finalized.final_id_ceiling => Ok(()),
+            _ => Err(AllocatorError::FinalIdSpaceExhausted),
         }
     }
 
@@ -174,6 +329,200 @@ impl IdCompressor {
         new_local
     }
 
+    /// Allocates `count` contiguous final IDs on behalf of the ghost session and returns the first.
+    /// The ghost session's tail cluster is only extended if it is the last cluster in final space; otherwise a new cluster of
+    /// exactly `count` IDs is created. The ghost session is added to the session table by its first allocation.
+    /// Ghost allocations are not reflected in telemetry.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::ClusterCollision`
+    /// >   * The stable IDs of the allocation would overlap those allocated to another session.
+    /// > * `AllocatorError::FinalIdSpaceExhausted`
+    /// >   * The allocation would exceed the final ID ceiling.
+    fn allocate_ghost_finals(
+        &mut self,
+        ghost_session_id: SessionId,
+        count: u64,
+    ) -> Result<FinalId, AllocatorError> {
+        // Ghost sessions only ever contain ghost clusters (see `begin_ghost_session`), whose capacity always equals their
+        // count. There is never spare capacity to fill, and so the allocation begins after the tail cluster's capacity.
+        let ghost_session_ref = self.finalized.sessions.get(ghost_session_id).copied();
+        let allocated_count = match ghost_session_ref.and_then(|ghost_session_ref| {
+            self.finalized
+                .sessions
+                .deref_session_space(ghost_session_ref)
+                .get_tail_cluster()
+        }) {
+            Some(tail_cluster) => {
+                debug_assert_eq!(tail_cluster.capacity, tail_cluster.count);
+                tail_cluster.base_local_id.to_generation_count() - 1 + tail_cluster.capacity
+            }
+            None => 0,
+        };
+        let range_base_stable = StableId::from(ghost_session_id)
+            .checked_add(allocated_count)
+            .ok_or(AllocatorError::StableIdOverflow)?;
+        let range_max_stable = range_base_stable
+            .checked_add(count - 1)
+            .ok_or(AllocatorError::StableIdOverflow)?;
+        // As with finalized ranges, the ghost session is absent from the session table until its first cluster is created,
+        // so that first cluster is checked against the closest session below it.
+        if self.finalized.sessions.range_collides(
+            ghost_session_id,
+            range_base_stable,
+            range_max_stable,
+        ) {
+            return Err(AllocatorError::ClusterCollision);
+        }
+        let final_id_ceiling = self.finalized.final_id_ceiling;
+        let last_cluster_base_final = self
+            .finalized
+            .final_space
+            .get_tail_cluster(&self.finalized.sessions)
+            .map(|cluster| cluster.base_final_id);
+        let first_final = match ghost_session_ref.and_then(|ghost_session_ref| {
+            self.finalized
+                .sessions
+                .deref_session_space_mut(ghost_session_ref)
+                .get_tail_cluster_mut()
+        }) {
+            Some(tail_cluster) if Some(tail_cluster.base_final_id) == last_cluster_base_final => {
+                let new_count = tail_cluster
+                    .count
+                    .checked_add(count)
+                    .ok_or(AllocatorError::FinalIdSpaceExhausted)?;
+                let new_capacity = tail_cluster.capacity.max(new_count);
+                match get_id_from_final_id(tail_cluster.base_final_id).checked_add(new_capacity) {
+                    Some(final_id_limit) if final_id_limit <= final_id_ceiling => {}
+                    _ => return Err(AllocatorError::FinalIdSpaceExhausted),
+                }
+                let first_final = tail_cluster.base_final_id + tail_cluster.count;
+                tail_cluster.count = new_count;
+                tail_cluster.capacity = new_capacity;
+                first_final
+            }
+            tail_cluster => {
+                let base_local = match tail_cluster {
+                    Some(tail_cluster) => tail_cluster.base_local_id - tail_cluster.capacity,
+                    None => local_id_from_id(-1),
+                };
+                // Checked before the session is created so that a failed allocation leaves no empty session behind.
+                self.finalized.check_final_id_ceiling(count)?;
+                let ghost_session_ref = self.finalized.sessions.get_or_create(ghost_session_id);
+                let new_cluster_ref =
+                    self.finalized
+                        .add_empty_cluster(ghost_session_ref, base_local, count)?;
+                let new_cluster = self.finalized.sessions.deref_cluster_mut(new_cluster_ref);
+                new_cluster.count = count;
+                new_cluster.base_final_id
+            }
+        };
+        self.finalized.refresh_final_id_limit();
+        Ok(first_final)
+    }
+
+    /// Generates `count` new session space IDs in bulk.
+    /// The result is equivalent to calling `generate_next_id` `count` times, but performs the tail cluster lookup and
+    /// normalizer update once. Returns a compact description of the generated IDs (see [GeneratedIds]).
+    ///
+    /// # Panics
+    /// If the IDs cannot be generated (see `IdCompressor::try_generate_n_ids`).
+    pub fn generate_n_ids(&mut self, count: u64) -> GeneratedIds {
+        self.try_generate_n_ids(count)
+            .expect("IDs must be generated.")
+    }
+
+    /// Generates `count` new session space IDs in bulk, as `IdCompressor::generate_n_ids`.
+    /// If any of the IDs cannot be generated, none are.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::FinalIdSpaceExhausted`
+    /// >   * The generation count of an ID, or a final ID allocated on behalf of a ghost session, would not be less than
+    /// >     the final ID ceiling of the compressor.
+    /// > * `AllocatorError::ClusterCollision`
+    /// >   * The final IDs allocated on behalf of a ghost session would collide with the IDs of another session.
+    pub fn try_generate_n_ids(&mut self, count: u64) -> Result<GeneratedIds, AllocatorError> {
+        if let Some(ghost_session_id) = self.ghost_session_id {
+            return Ok(GeneratedIds {
+                eager_finals: match count {
+                    0 => None,
+                    _ => Some((
+                        self.allocate_ghost_finals(ghost_session_id, count)?.into(),
+                        count,
+                    )),
+                },
+                locals: None,
+            });
+        }
+        self.check_generation_ceiling(count)?;
+        let first_generation_count = self.generated_id_count + 1;
+        let eager_finals = match self.get_local_session_space().get_tail_cluster() {
+            Some(tail_cluster) => {
+                let cluster_offset =
+                    first_generation_count - tail_cluster.base_local_id.to_generation_count();
+                match tail_cluster
+                    .capacity
+                    .saturating_sub(cluster_offset)
+                    .min(count)
+                {
+                    0 => None,
+                    eager_final_count => Some((
+                        SessionSpaceId::from(tail_cluster.base_final_id + cluster_offset),
+                        eager_final_count,
+                    )),
+                }
+            }
+            None => None,
+        };
+        let eager_final_count = eager_finals.map_or(0, |(_, eager_count)| eager_count);
+        let local_count = count - eager_final_count;
+        let locals = if local_count == 0 {
+            None
+        } else {
+            let first_local =
+                LocalId::from_generation_count(first_generation_count + eager_final_count);
+            self.session_space_normalizer
+                .add_local_range(first_local, local_count);
+            Some((SessionSpaceId::from(first_local), local_count))
+        };
+        self.generated_id_count += count;
+        self.telemetry_stats.eager_final_count += eager_final_count;
+        self.telemetry_stats.local_id_count += local_count;
+        Ok(GeneratedIds {
+            eager_finals,
+            locals,
+        })
+    }
+
+    /// Retracts the `count` most recently generated IDs, which will be reissued by subsequent ID generation.
+    /// Intended for abandoning IDs generated for an action that was never sent, so that they are not included in the next range.
+    /// Telemetry is not adjusted for retracted IDs.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidRetraction`
+    /// >   * Some of the IDs have already been taken in a range, or are eager finals.
+    pub fn retract_ids(&mut self, count: u64) -> Result<(), AllocatorError> {
+        if count == 0 {
+            return Ok(());
+        }
+        let unranged_count = self.generated_id_count - (self.next_range_base_generation_count - 1);
+        if count > unranged_count {
+            return Err(AllocatorError::InvalidRetraction);
+        }
+        // Trailing locals are merged into a single normalizer range, so it must end at the most recently generated ID
+        // and contain every retracted ID.
+        match self.session_space_normalizer.get_ranges().last() {
+            Some(&(base_local, local_count))
+                if base_local.to_generation_count() - 1 + local_count
+                    == self.generated_id_count
+                    && local_count >= count => {}
+            _ => return Err(AllocatorError::InvalidRetraction),
+        }
+        self.session_space_normalizer.remove_trailing_locals(count);
+        self.generated_id_count -= count;
+        Ok(())
+    }
+
     /// Returns current compressor state telemetry.
     /// Intended for logging and analysis.
     pub fn get_telemetry_stats(&mut self) -> TelemetryStats {
@@ -196,126 +545,146 @@ impl IdCompressor {
                     "Must only allocate a positive number of IDs. Count was {}",
                     count
                 );
-                let next_range = Some((self.next_range_base_generation_count, count));
+                let next_range = (self.next_range_base_generation_count, count);
+                self.pending_ranges
+                    .push((next_range.0, next_range.1, self.capacity_hint));
                 self.next_range_base_generation_count = self.generated_id_count + 1;
-                next_range
+                Some(next_range)
             },
+            capacity_hint: self.capacity_hint,
         }
     }
 
-    /// Finalizes the supplied range of IDs (which may be from either a remote or local session).
-    /// This method relies on a total order broadcast service.
-    /// Ordered operation acknowledgement must call this method, and this method must only be called with ordered ranges.
-    pub fn finalize_range(
-        &mut self,
-        &IdRange {
-            id: session_id,
-            range,
-        }: &IdRange,
-    ) -> Result<(), AllocatorError> {
-        // Check if the range has IDs
-        let (range_base_gen_count, range_len) = match range {
-            None => {
-                return Ok(());
-            }
-            Some((_, 0)) => {
-                return Err(AllocatorError::MalformedIdRange);
-            }
-            Some(range) => range,
-        };
+    /// Returns the ranges taken by `IdCompressor::take_next_range` that have not yet been finalized, in the order they were taken.
+    /// Ranges that have been partially finalized contain only their unfinalized IDs.
+    /// Each range carries the capacity hint that was in effect when it was taken.
+    pub fn get_pending_ranges(&self) -> Vec<IdRange> {
+        self.pending_ranges
+            .iter()
+            .map(|&(base, count, capacity_hint)| IdRange {
+                id: self.session_id,
+                range: Some((base, count)),
+                capacity_hint,
+            })
+            .collect()
+    }
 
-        let range_base_local = LocalId::from_generation_count(range_base_gen_count);
-        let range_base_stable = StableId::from(session_id) + range_base_local;
-        // Checks collision for the maximum new cluster span (the condition in which the current tail cluster is exactly full)
-        if self.sessions.range_collides(
-            session_id,
-            range_base_stable,
-            range_base_stable + range_len + self.cluster_capacity,
-        ) {
-            return Err(AllocatorError::ClusterCollision);
-        }
-        let session_space_ref = self.sessions.get_or_create(session_id);
-        if self
-            .sessions
-            .deref_session_space_mut(session_space_ref)
-            .cluster_chain_is_empty()
-        {
-            // This is the first cluster in the session space
-            if range_base_local != -1 {
-                return Err(AllocatorError::RangeFinalizedOutOfOrder);
+    /// Returns the pending ranges of this compressor (see `IdCompressor::get_pending_ranges`), with contiguous ranges
+    /// taken under the same capacity hint merged.
+    /// Intended for resubmission to the ordering service after reconnecting. Pending ranges are retained until finalized.
+    pub fn resubmit_pending_ranges(&self) -> Vec<IdRange> {
+        let mut merged: Vec<(u64, u64, Option<u64>)> = Vec::new();
+        for &(base, count, capacity_hint) in &self.pending_ranges {
+            match merged.last_mut() {
+                Some((last_base, last_count, last_capacity_hint))
+                    if *last_base + *last_count == base && *last_capacity_hint == capacity_hint =>
+                {
+                    *last_count += count
+                }
+                _ => merged.push((base, count, capacity_hint)),
             }
-            self.telemetry_stats.cluster_creation_count += 1;
-            _ = self.add_empty_cluster(
-                session_space_ref,
-                range_base_local,
-                self.cluster_capacity + range_len,
-            );
-        };
-        let last_cluster_base_final = self
-            .final_space
-            .get_tail_cluster(&self.sessions)
-            .unwrap()
-            .base_final_id;
-        let tail_cluster = self
-            .sessions
-            .deref_session_space_mut(session_space_ref)
-            .get_tail_cluster_mut()
-            .unwrap();
-        let remaining_capacity = tail_cluster.capacity - tail_cluster.count;
-        if tail_cluster.base_local_id - tail_cluster.count != range_base_local {
-            return Err(AllocatorError::RangeFinalizedOutOfOrder);
         }
-        if remaining_capacity >= range_len {
-            // The current range fits in the existing cluster
-            tail_cluster.count += range_len;
-        } else {
-            let overflow = range_len - remaining_capacity;
-            let new_claimed_final_count = overflow + self.cluster_capacity;
-            if tail_cluster.base_final_id == last_cluster_base_final {
-                // Tail_cluster is the last cluster, and so can be expanded.
-                self.telemetry_stats.expansion_count += 1;
-                tail_cluster.capacity += new_claimed_final_count;
-                tail_cluster.count += range_len;
-            } else {
-                // Tail cluster is not the last cluster. Fill and overflow to new.
-                self.telemetry_stats.cluster_creation_count += 1;
-                tail_cluster.count = tail_cluster.capacity;
-                let new_cluster_ref = self.add_empty_cluster(
-                    session_space_ref,
-                    range_base_local - remaining_capacity,
-                    new_claimed_final_count,
-                );
-                self.sessions.deref_cluster_mut(new_cluster_ref).count += overflow;
+        merged
+            .into_iter()
+            .map(|(base, count, capacity_hint)| IdRange {
+                id: self.session_id,
+                range: Some((base, count)),
+                capacity_hint,
+            })
+            .collect()
+    }
+
+    // Removes all finalized IDs from the pending ranges.
+    fn prune_pending_ranges(&mut self) {
+        let finalized_count = match self.get_local_session_space().get_tail_cluster() {
+            Some(tail_cluster) => {
+                tail_cluster.base_local_id.to_generation_count() - 1 + tail_cluster.count
             }
-        }
-        self.final_id_limit = match self.final_space.get_tail_cluster(&self.sessions) {
-            Some(cluster) => cluster.base_final_id + cluster.count,
-            None => self.final_id_limit,
+            None => return,
         };
-        Ok(())
+        self.pending_ranges.retain_mut(|(base, count, _)| {
+            if *base + *count - 1 <= finalized_count {
+                return false;
+            }
+            if *base <= finalized_count {
+                *count -= finalized_count + 1 - *base;
+                *base = finalized_count + 1;
+            }
+            true
+        });
+    }
+
+    /// Finalizes the supplied range of IDs (which may be from either a remote or local session).
+    /// This method relies on a total order broadcast service.
+    /// Ordered operation acknowledgement must call this method, and this method must only be called with ordered ranges.
+    pub fn finalize_range(&mut self, id_range: &IdRange) -> Result<(), AllocatorError> {
+        self.finalize_range_with_outcome(id_range).map(|_| ())
     }
 
-    fn add_empty_cluster(
+    /// Finalizes the supplied range of IDs, as `IdCompressor::finalize_range`.
+    /// Returns a [FinalizationOutcome] describing the IDs finalized and any clusters created or expanded.
+    pub fn finalize_range_with_outcome(
         &mut self,
-        session_space_ref: SessionSpaceRef,
-        base_local: LocalId,
-        capacity: u64,
-    ) -> ClusterRef {
-        let next_base_final = match self.final_space.get_tail_cluster(&self.sessions) {
-            Some(cluster) => cluster.base_final_id + cluster.capacity,
-            None => final_id_from_id(0),
-        };
-        let session_space = self.sessions.deref_session_space_mut(session_space_ref);
-        let new_cluster_ref = session_space.add_empty_cluster(
-            session_space_ref,
-            next_base_final,
-            base_local,
-            capacity,
-        );
-        self.final_space
-            .add_cluster(new_cluster_ref, &self.sessions);
+        id_range: &IdRange,
+    ) -> Result<FinalizationOutcome, AllocatorError> {
+        let outcome = self.finalized.finalize_range(id_range)?;
+        self.telemetry_stats
+            .record_finalization(&outcome, id_range.id == self.session_id);
+        self.prune_pending_ranges();
+        Ok(outcome)
+    }
+
+    /// Checks whether the supplied range of IDs would finalize cleanly, without modifying the compressor.
+    /// Returns a [PlannedFinalization] describing where the range would be placed and which final IDs it would receive.
+    ///
+    /// > # Errors
+    /// > * Any error that would be returned by `IdCompressor::finalize_range`.
+    pub fn check_range(&self, id_range: &IdRange) -> Result<PlannedFinalization, AllocatorError> {
+        self.finalized.plan_finalization(id_range)
+    }
 
-        new_cluster_ref
+    /// Finalizes the supplied ranges of IDs in order, as `IdCompressor::finalize_range_with_outcome`.
+    /// The batch is atomic: if any range fails to finalize, no range in the batch is finalized.
+    /// Returns the [FinalizationOutcome] of each range, in order.
+    ///
+    /// > # Errors
+    /// > * [BatchFinalizationError]
+    /// >   * Contains the index of the first range that failed to finalize, and the reason it failed.
+    pub fn finalize_ranges(
+        &mut self,
+        id_ranges: &[IdRange],
+    ) -> Result<Vec<FinalizationOutcome>, BatchFinalizationError> {
+        let outcomes = self.finalized.finalize_ranges(id_ranges)?;
+        for (id_range, outcome) in id_ranges.iter().zip(&outcomes) {
+            self.telemetry_stats
+                .record_finalization(outcome, id_range.id == self.session_id);
+        }
+        self.prune_pending_ranges();
+        Ok(outcomes)
+    }
+
+    /// Finalizes the supplied range of IDs, as `IdCompressor::finalize_range_with_outcome`, unless the range has the same
+    /// base and length as a range its session has already finalized, in which case it is treated as a replay and ignored.
+    /// Intended for use when an ordering service may redeliver ranges that have already been finalized.
+    /// Other ranges of finalized IDs (e.g. sub-ranges or merges of finalized ranges) are rejected as out of order, as are
+    /// replays of ranges finalized before the compressor was loaded from a format predating the persistence of range
+    /// boundaries.
+    /// Returns an empty [FinalizationOutcome] for replayed ranges.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::MalformedIdRange`
+    /// >   * The range is empty, begins at generation count zero, or extends past the largest generation count.
+    /// > * `AllocatorError::RangePartiallyFinalized`
+    /// >   * The range contains both finalized and unfinalized IDs.
+    /// > * Any error returned by `IdCompressor::finalize_range`.
+    pub fn finalize_range_idempotent(
+        &mut self,
+        id_range: &IdRange,
+    ) -> Result<FinalizationOutcome, AllocatorError> {
+        if self.finalized.is_range_finalized(id_range)? {
+            return Ok(FinalizationOutcome::EMPTY);
+        }
+        self.finalize_range_with_outcome(id_range)
     }
 
     /// Normalizes a session space ID to op space.
@@ -341,6 +710,163 @@ impl IdCompressor {
         }
     }
 
+    /// Returns the [IdStatus] of the provided [SessionSpaceId], including the session that created it.
+    /// IDs that are not valid in this session's session space (i.e. that would fail to decompress) are `IdStatus::Unknown`.
+    pub fn get_status(&self, id: SessionSpaceId) -> IdStatus {
+        match id.to_space() {
+            CompressedId::Local(local_id) => {
+                if !self.session_space_normalizer.contains(local_id) {
+                    return IdStatus::Unknown;
+                }
+                match self
+                    .get_local_session_space()
+                    .try_convert_to_final(local_id, false)
+                {
+                    Some(final_id) => IdStatus::FinalizedLocal(self.session_id, final_id),
+                    None => IdStatus::UnfinalizedLocal(self.session_id),
+                }
+            }
+            CompressedId::Final(final_id) => match self.get_final_origin(final_id) {
+                Some((session_space_ref, is_finalized)) => {
+                    let session_id = self.finalized.sessions.get_session_id(session_space_ref);
+                    if is_finalized {
+                        IdStatus::Final(session_id)
+                    } else {
+                        IdStatus::EagerFinal(session_id)
+                    }
+                }
+                None => IdStatus::Unknown,
+            },
+        }
+    }
+
+    /// Returns the session that created the provided [SessionSpaceId].
+    /// Intended for attributing authorship; see `IdCompressor::get_originating_session_token` for a faster variant.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidSessionSpaceId`
+    /// >   * The provided [SessionSpaceId] has not been allocated.
+    pub fn get_originating_session(&self, id: SessionSpaceId) -> Result<SessionId, AllocatorError> {
+        let session_space_ref = self.get_originating_session_ref(id)?;
+        Ok(self.finalized.sessions.get_session_id(session_space_ref))
+    }
+
+    /// Returns a token representing the session that created the provided [SessionSpaceId].
+    /// The returned token is the same as that returned by `IdCompressor::get_session_token_from_session_id` for the creating session.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidSessionSpaceId`
+    /// >   * The provided [SessionSpaceId] has not been allocated.
+    pub fn get_originating_session_token(&self, id: SessionSpaceId) -> Result<i64, AllocatorError> {
+        Ok(self.get_originating_session_ref(id)?.get_index() as i64)
+    }
+
+    fn get_originating_session_ref(
+        &self,
+        id: SessionSpaceId,
+    ) -> Result<SessionSpaceRef, AllocatorError> {
+        match id.to_space() {
+            CompressedId::Local(local_id) => {
+                if self.session_space_normalizer.contains(local_id) {
+                    Ok(self.local_session_ref)
+                } else {
+                    Err(AllocatorError::InvalidSessionSpaceId)
+                }
+            }
+            CompressedId::Final(final_id) => self
+                .get_final_origin(final_id)
+                .map(|(session_space_ref, _)| session_space_ref)
+                .ok_or(AllocatorError::InvalidSessionSpaceId),
+        }
+    }
+
+    // Returns the session space of the session that created a final ID, and whether the ID has been finalized.
+    // Returns None if the final ID is not valid in this session's session space.
+    fn get_final_origin(&self, final_id: FinalId) -> Option<(SessionSpaceRef, bool)> {
+        let containing_cluster_ref = self
+            .finalized
+            .final_space
+            .search(final_id, &self.finalized.sessions)?;
+        let containing_cluster = self
+            .finalized
+            .sessions
+            .deref_cluster(containing_cluster_ref);
+        let aligned_local = containing_cluster.get_aligned_local(final_id)?;
+        let containing_session_space = containing_cluster_ref.get_session_space_ref();
+        if aligned_local >= containing_cluster.max_local() {
+            Some((containing_session_space, true))
+        } else if containing_session_space == self.local_session_ref
+            && !self.session_space_normalizer.contains(aligned_local)
+            && aligned_local.to_generation_count() <= self.generated_id_count
+        {
+            Some((containing_session_space, false))
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over every finalized ID in the document, in ascending final ID order.
+    /// Yields the [SessionSpaceId] form of each ID in this session along with its [StableId] equivalent.
+    /// Allocated but unfinalized IDs are skipped.
+    pub fn iter_finalized_ids(&self) -> impl Iterator<Item = (SessionSpaceId, StableId)> + '_ {
+        self.finalized
+            .final_space
+            .get_clusters(&self.finalized.sessions)
+            .flat_map(move |(cluster, cluster_ref)| {
+                self.iter_cluster_ids(cluster, cluster_ref.get_session_space_ref())
+            })
+    }
+
+    /// Returns an iterator over every finalized ID created by the supplied session, in the order they were generated.
+    /// Yields the [SessionSpaceId] form of each ID in this session along with its [StableId] equivalent.
+    /// Allocated but unfinalized IDs are skipped.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::NoTokenForSession`
+    /// >   * No known session for the provided [SessionId].
+    pub fn iter_session_ids(
+        &self,
+        session_id: SessionId,
+    ) -> Result<impl Iterator<Item = (SessionSpaceId, StableId)> + '_, AllocatorError> {
+        let session_space_ref = *self
+            .finalized
+            .sessions
+            .get(session_id)
+            .ok_or(AllocatorError::NoTokenForSession)?;
+        Ok(self
+            .finalized
+            .sessions
+            .deref_session_space(session_space_ref)
+            .get_clusters()
+            .flat_map(move |cluster| self.iter_cluster_ids(cluster, session_space_ref)))
+    }
+
+    // Iterates the finalized IDs of a cluster, yielding the session space form of each along with its stable ID.
+    fn iter_cluster_ids(
+        &self,
+        cluster: &IdCluster,
+        session_space_ref: SessionSpaceRef,
+    ) -> impl Iterator<Item = (SessionSpaceId, StableId)> + '_ {
+        let session_id = self.finalized.sessions.get_session_id(session_space_ref);
+        let is_local_session = session_space_ref == self.local_session_ref;
+        let &IdCluster {
+            base_final_id,
+            base_local_id,
+            count,
+            ..
+        } = cluster;
+        (0..count).map(move |offset| {
+            let local_id = base_local_id - offset;
+            let session_space_id =
+                if is_local_session && self.session_space_normalizer.contains(local_id) {
+                    SessionSpaceId::from(local_id)
+                } else {
+                    SessionSpaceId::from(base_final_id + offset)
+                };
+            (session_space_id, session_id + local_id)
+        })
+    }
+
     /// Normalizes an op space ID to this session's session space.
     /// Requires the originator's session ID as a [SessionId].
     /// Note: Originator denotes the session sending the operation, not the creator of the ID.
@@ -405,8 +931,10 @@ impl IdCompressor {
                     }
                 } else {
                     // LocalId from a foreign session
-                    let foreign_session_space = self.sessions.deref_session_space(originator_ref);
-                    match foreign_session_space.try_convert_to_final(local_to_normalize, false) {
+                    match self
+                        .finalized
+                        .try_convert_finalized_local(originator_ref, local_to_normalize)
+                    {
                         Some(final_id) => Ok(SessionSpaceId::from(final_id)),
                         None => Err(AllocatorError::InvalidOpSpaceId),
                     }
@@ -434,7 +962,7 @@ impl IdCompressor {
                     }
                     None => {
                         // Does not exist in local cluster chain
-                        if final_to_normalize >= self.final_id_limit {
+                        if final_to_normalize >= self.finalized.final_id_limit {
                             Err(AllocatorError::InvalidOpSpaceId)
                         } else {
                             Ok(SessionSpaceId::from(final_to_normalize))
@@ -452,13 +980,21 @@ impl IdCompressor {
     /// > # Errors
     /// > * `AllocatorError::InvalidSessionSpaceId`
     /// >   * Failed to decompress the provided [SessionSpaceId].
+    /// > * `AllocatorError::StableIdOverflow`
+    /// >   * The provided [SessionSpaceId] is a local whose stable ID would exceed the UUID space.
     pub fn decompress(&self, id: SessionSpaceId) -> Result<StableId, AllocatorError> {
         match id.to_space() {
             CompressedId::Final(final_id) => {
-                match self.final_space.search(final_id, &self.sessions) {
+                match self
+                    .finalized
+                    .final_space
+                    .search(final_id, &self.finalized.sessions)
+                {
                     Some(containing_cluster_ref) => {
-                        let containing_cluster =
-                            self.sessions.deref_cluster(containing_cluster_ref);
+                        let containing_cluster = self
+                            .finalized
+                            .sessions
+                            .deref_cluster(containing_cluster_ref);
                         let containing_session_space =
                             containing_cluster_ref.get_session_space_ref();
                         let aligned_local = match containing_cluster.get_aligned_local(final_id) {
@@ -479,7 +1015,11 @@ impl IdCompressor {
                             }
                         }
 
-                        Ok(self.sessions.get_session_id(containing_session_space) + aligned_local)
+                        Ok(self
+                            .finalized
+                            .sessions
+                            .get_session_id(containing_session_space)
+                            + aligned_local)
                     }
                     None => Err(AllocatorError::InvalidSessionSpaceId),
                 }
@@ -488,7 +1028,9 @@ impl IdCompressor {
                 if !self.session_space_normalizer.contains(local_id) {
                     return Err(AllocatorError::InvalidSessionSpaceId);
                 }
-                Ok(self.session_id + local_id)
+                StableId::from(self.session_id)
+                    .checked_add(local_id.to_generation_count() - 1)
+                    .ok_or(AllocatorError::StableIdOverflow)
             }
         }
     }
@@ -500,7 +1042,7 @@ impl IdCompressor {
     /// > * `AllocatorError::InvalidStableId`
     /// >   * Failed to recompress the provided `StableId`.
     pub fn recompress(&self, id: StableId) -> Result<SessionSpaceId, AllocatorError> {
-        match self.sessions.get_containing_cluster(id) {
+        match self.finalized.sessions.get_containing_cluster(id) {
             None => {
                 let session_as_stable = StableId::from(self.session_id);
                 if id >= session_as_stable {
@@ -547,16 +1089,23 @@ impl IdCompressor {
         }
     }
 
+    /// Returns a snapshot of the layout of every cluster in the document, in ascending base final ID order.
+    /// Intended for analysis of cluster capacity and fragmentation.
+    pub fn get_cluster_layout(&self) -> Vec<ClusterSnapshot> {
+        self.finalized.get_cluster_layout()
+    }
+
+    /// Returns a report of the final ID space reserved and used by the clusters of each session and of the document.
+    pub fn space_report(&self) -> SpaceReport {
+        self.finalized.space_report()
+    }
+
     /// Returns a persistable form of the current state of this `IdCompressor`, either with or without local state.
     /// Serializing without local state includes only finalized state, and is therefore suitable for use in summaries.
     /// Serializing with local state includes finalized state as well as un-finalized state and is therefore suitable for use in offline scenarios.
     /// Either form can be rehydrated via `IdCompressor::deserialize()`.
     pub fn serialize(&self, include_local_state: bool) -> Vec<u8> {
-        if !include_local_state {
-            persistence::v1::serialize(self)
-        } else {
-            persistence::v1::serialize_with_local(self)
-        }
+        persistence::v8::serialize(self, include_local_state)
     }
 
     #[cfg(feature = "uuid-generation")]
@@ -577,6 +1126,25 @@ impl IdCompressor {
     {
         persistence::deserialize(bytes, make_session_id)
     }
+
+    /// Rehydrates a serialized `IdCompressor` as `IdCompressor::deserialize_with_session_id_generator()` does, and
+    /// additionally rejects state that fails `IdCompressor::validate()`.
+    /// Suitable for rehydrating state from untrusted sources.
+    pub fn deserialize_with_validation<FMakeSession>(
+        bytes: &[u8],
+        make_session_id: FMakeSession,
+    ) -> Result<IdCompressor, DeserializationError>
+    where
+        FMakeSession: FnOnce() -> SessionId,
+    {
+        let compressor = persistence::deserialize(bytes, make_session_id)?;
+        let report = compressor.validate();
+        if report.is_valid() {
+            Ok(compressor)
+        } else {
+            Err(DeserializationError::FailedValidation(report))
+        }
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -584,15 +1152,7 @@ impl IdCompressor {
     /// Checks equality across [IdCompressor]_s.
     /// Debug-only, intended for testing.
     pub fn equals_test_only(&self, other: &IdCompressor, compare_local_state: bool) -> bool {
-        if !(self.final_id_limit == other.final_id_limit
-            && self.sessions.equals_test_only(&other.sessions)
-            && self.final_space.equals_test_only(
-                &other.final_space,
-                &self.sessions,
-                &other.sessions,
-            )
-            && self.cluster_capacity == other.cluster_capacity)
-        {
+        if !self.finalized.equals_test_only(&other.finalized) {
             false
         } else {
             !(compare_local_state
@@ -600,6 +1160,7 @@ impl IdCompressor {
                     && self.generated_id_count == other.generated_id_count
                     && self.next_range_base_generation_count
                         == other.next_range_base_generation_count
+                    && self.pending_ranges == other.pending_ranges
                     && self.session_space_normalizer == other.session_space_normalizer))
         }
     }
@@ -612,6 +1173,180 @@ pub struct IdRange {
     pub id: SessionId,
     /// A Some(range) will contain a tuple of u64s representing `(First ID, count of IDs)`.
     pub range: Option<(u64, u64)>,
+    /// A Some(capacity) requests that clusters created or expanded to finalize the range reserve `capacity` IDs, in place of the
+    /// document's cluster capacity. Capacities greater than the document's maximum capacity hint are clamped to it.
+    pub capacity_hint: Option<u64>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A compact description of the IDs created by a single call to `IdCompressor::generate_n_ids`.
+/// IDs are generated as a run of eager finals (from the local session's tail cluster) followed by a run of locals.
+/// While a ghost session is in progress, all IDs are finals allocated on behalf of the ghost session.
+pub struct GeneratedIds {
+    /// A Some(run) will contain a tuple of `(First eager final, count of eager finals)`. Eager finals ascend from the first.
+    pub eager_finals: Option<(SessionSpaceId, u64)>,
+    /// A Some(run) will contain a tuple of `(First local, count of locals)`. Locals descend from the first.
+    pub locals: Option<(SessionSpaceId, u64)>,
+}
+
+impl GeneratedIds {
+    /// Returns the total number of IDs generated.
+    pub fn count(&self) -> u64 {
+        self.eager_finals.map_or(0, |(_, count)| count) + self.locals.map_or(0, |(_, count)| count)
+    }
+
+    /// Returns an iterator over the generated IDs, in generation order.
+    pub fn iter(&self) -> impl Iterator<Item = SessionSpaceId> {
+        let eager_finals = self.eager_finals.into_iter().flat_map(|(first, count)| {
+            (0..count as i64).map(move |offset| SessionSpaceId::from_id(first.id() + offset))
+        });
+        let locals = self.locals.into_iter().flat_map(|(first, count)| {
+            (0..count as i64).map(move |offset| SessionSpaceId::from_id(first.id() - offset))
+        });
+        eager_finals.chain(locals)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The status of a compressed ID with respect to the finalized state of a document.
+pub enum IdStatus {
+    /// A local ID that has not yet been finalized.
+    /// Contains `(creating session)`.
+    UnfinalizedLocal(SessionId),
+    /// A local ID that has been finalized.
+    /// Contains `(creating session, final form of the ID)`.
+    FinalizedLocal(SessionId, FinalId),
+    /// A final ID generated by the local session that has not yet been finalized.
+    /// Contains `(creating session)`.
+    EagerFinal(SessionId),
+    /// A final ID that has been finalized.
+    /// Contains `(creating session)`.
+    Final(SessionId),
+    /// An ID that is not valid in the queried space.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A description of the effects of finalizing a single [IdRange].
+pub struct FinalizationOutcome {
+    /// The spans of local IDs that were finalized, in ascending generation order.
+    /// A range spans two clusters when it overflows a tail cluster that cannot be expanded.
+    pub spans: Vec<FinalizedSpan>,
+    /// The clusters created or expanded to contain the range, in the order they occurred.
+    pub cluster_events: Vec<ClusterEvent>,
+}
+
+impl FinalizationOutcome {
+    const EMPTY: FinalizationOutcome = FinalizationOutcome {
+        spans: Vec::new(),
+        cluster_events: Vec::new(),
+    };
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A run of consecutive local IDs that were finalized into consecutive final IDs.
+pub struct FinalizedSpan {
+    /// The first local ID of the span. Locals descend from the first.
+    pub base_local: LocalId,
+    /// The final ID equivalent of the first local. Finals ascend from the first.
+    pub base_final: FinalId,
+    /// The count of IDs in the span.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A description of how a single [IdRange] would be finalized, computed without modifying the compressor.
+pub struct PlannedFinalization {
+    /// Where the range would be placed in the cluster chain of its session.
+    pub placement: RangePlacement,
+    /// The spans of local IDs that would be finalized, in ascending generation order.
+    pub spans: Vec<FinalizedSpan>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// The placement of a range within the cluster chain of its session.
+pub enum RangePlacement {
+    /// The range contains no IDs.
+    Empty,
+    /// The range would create the first cluster of its session.
+    /// Contains `(base final ID of the cluster, capacity of the cluster)`.
+    CreatesFirstCluster(FinalId, u64),
+    /// The range would fit within the remaining capacity of the session's tail cluster.
+    FitsTailCluster,
+    /// The range would expand the session's tail cluster.
+    /// Contains `(base final ID of the cluster, capacity added)`.
+    ExpandsTailCluster(FinalId, u64),
+    /// The range would fill the session's tail cluster and overflow to a new cluster.
+    /// Contains `(base final ID of the new cluster, capacity of the new cluster)`.
+    OverflowsToNewCluster(FinalId, u64),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A read-only snapshot of a single cluster of the finalized state of a document.
+pub struct ClusterSnapshot {
+    /// The session that owns the cluster.
+    pub session_id: SessionId,
+    /// The first final ID of the cluster. Finals ascend from the first.
+    pub base_final_id: FinalId,
+    /// The first local ID of the cluster. Locals descend from the first.
+    pub base_local_id: LocalId,
+    /// The count of IDs allocated to the cluster.
+    pub capacity: u64,
+    /// The count of IDs finalized in the cluster.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A summary of how the final ID space of a document is reserved and used by clusters.
+/// IDs that are reserved by a cluster but not yet finalized are unused; excess unused IDs indicate that the cluster
+/// capacity is too large, while a high cluster count indicates that it is too small.
+pub struct SpaceReport {
+    /// The count of clusters in the document.
+    pub cluster_count: u64,
+    /// The count of final IDs reserved by all clusters.
+    pub capacity: u64,
+    /// The count of final IDs finalized in all clusters.
+    pub count: u64,
+    /// The count of final IDs reserved by clusters but not finalized.
+    pub unused_count: u64,
+    /// Per-session reports for every session that owns a cluster, in ascending session ID order.
+    pub sessions: Vec<SessionSpaceReport>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A summary of how the clusters of a single session reserve and use final IDs.
+pub struct SessionSpaceReport {
+    /// The session that owns the clusters.
+    pub session_id: SessionId,
+    /// The count of clusters owned by the session.
+    pub cluster_count: u64,
+    /// The count of final IDs reserved by the session's clusters.
+    pub capacity: u64,
+    /// The count of final IDs finalized in the session's clusters.
+    pub count: u64,
+    /// The count of final IDs reserved by the session's clusters but not finalized.
+    pub unused_count: u64,
+}
+
+#[derive(Debug)]
+/// The error returned when a batch of ranges fails to finalize.
+/// No range in a batch that fails is finalized.
+pub struct BatchFinalizationError {
+    /// The index within the batch of the first range that failed to finalize.
+    pub index: usize,
+    /// The reason the range failed to finalize.
+    pub error: AllocatorError,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A change to the clusters of a session caused by finalizing a range.
+pub enum ClusterEvent {
+    /// A new cluster was created.
+    /// Contains `(base final ID of the cluster, capacity of the cluster)`.
+    Created(FinalId, u64),
+    /// The tail cluster of the session was expanded.
+    /// Contains `(base final ID of the cluster, capacity added)`.
+    Expanded(FinalId, u64),
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -626,6 +1361,10 @@ pub struct TelemetryStats {
     pub expansion_count: u64,
     /// Count of new clusters created.
     pub cluster_creation_count: u64,
+    /// Count of instances of tail cluster expansion in the local session.
+    pub local_expansion_count: u64,
+    /// Count of new clusters created in the local session.
+    pub local_cluster_creation_count: u64,
 }
 
 impl TelemetryStats {
@@ -634,5 +1373,23 @@ impl TelemetryStats {
         local_id_count: 0,
         expansion_count: 0,
         cluster_creation_count: 0,
+        local_expansion_count: 0,
+        local_cluster_creation_count: 0,
     };
+
+    // Records the cluster events of a finalization, which is of the local session's IDs if `is_local` is true.
+    fn record_finalization(&mut self, outcome: &FinalizationOutcome, is_local: bool) {
+        for event in &outcome.cluster_events {
+            match event {
+                ClusterEvent::Created(..) => {
+                    self.cluster_creation_count += 1;
+                    self.local_cluster_creation_count += u64::from(is_local);
+                }
+                ClusterEvent::Expanded(..) => {
+                    self.expansion_count += 1;
+                    self.local_expansion_count += u64::from(is_local);
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,72 @@
+use super::persistence::DeserializationError;
+use super::persistence_utils::{write_varint_to_vec, Deserializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An op changing the cluster capacity of a document.
+/// Produced by one client via `IdCompressor::propose_capacity_change`, sequenced, and then applied by every compressor via
+/// `apply_capacity_change` in the same total order as finalized ranges.
+/// Carrying the capacity it replaces allows a compressor whose capacity has diverged to detect it rather than silently
+/// allocating differently from its peers.
+pub struct CapacityChange {
+    /// The cluster capacity the change was proposed against.
+    pub previous_capacity: u64,
+    /// The cluster capacity to use for new clusters once the change is applied.
+    pub new_capacity: u64,
+}
+
+impl CapacityChange {
+    /// Returns a persistable form of this change, suitable for transmission as an op.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_varint_to_vec(&mut bytes, self.previous_capacity);
+        write_varint_to_vec(&mut bytes, self.new_capacity);
+        bytes
+    }
+
+    /// Rehydrates a serialized `CapacityChange`.
+    pub fn deserialize(bytes: &[u8]) -> Result<CapacityChange, DeserializationError> {
+        let mut deserializer = Deserializer::new(bytes);
+        let change = CapacityChange {
+            previous_capacity: deserializer.take_varint()?,
+            new_capacity: deserializer.take_varint()?,
+        };
+        if change.new_capacity == 0 || !deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let change = CapacityChange {
+            previous_capacity: 512,
+            new_capacity: 3,
+        };
+        assert_eq!(CapacityChange::deserialize(&change.serialize()), Ok(change));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let zero_capacity = CapacityChange {
+            previous_capacity: 512,
+            new_capacity: 0,
+        };
+        let mut trailing = CapacityChange {
+            previous_capacity: 512,
+            new_capacity: 3,
+        }
+        .serialize();
+        trailing.push(0);
+        for bytes in [zero_capacity.serialize(), trailing, vec![]] {
+            assert_eq!(
+                CapacityChange::deserialize(&bytes),
+                Err(DeserializationError::MalformedInput)
+            );
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use super::TelemetryStats;
+use id_types::AllocatorError;
+
+/// A policy for choosing the cluster capacity of a document from observed allocation statistics.
+/// Policies only propose changes: the caller is responsible for sequencing each proposal as a [CapacityChange](super::CapacityChange) and applying
+/// it to every replica via `apply_capacity_change` in the same total order, or divergence will occur.
+pub trait ClusterCapacityPolicy {
+    /// Returns a new cluster capacity if the policy recommends a change, or None if the current capacity should be kept.
+    /// `stats` should contain the telemetry observed since the previous call (see `IdCompressor::get_telemetry_stats`).
+    /// Note that the ID counts of a compressor's telemetry cover only its local session, while its cluster counts cover
+    /// every session in the document unless prefixed with `local`.
+    fn propose_capacity(&mut self, current_capacity: u64, stats: &TelemetryStats) -> Option<u64>;
+}
+
+#[derive(Debug, Clone)]
+/// A [ClusterCapacityPolicy] that sizes clusters to the number of IDs allocated per cluster creation or expansion.
+/// Capacity is grown when more than `capacity` IDs are allocated per cluster creation or expansion, and shrunk when fewer
+/// than a quarter of `capacity` IDs are. Proposals are powers of two within the configured bounds.
+/// Statistics are accumulated across calls until a minimum sample of generated IDs has been observed.
+/// Both IDs and cluster events are counted for the local session only (see `TelemetryStats::local_expansion_count`), and
+/// so the ratio reflects the allocation pattern of the compressor whose telemetry is supplied.
+pub struct AdaptiveCapacityPolicy {
+    min_capacity: u64,
+    max_capacity: u64,
+    min_sample_count: u64,
+    // Statistics accumulated since the last proposal was evaluated.
+    id_count: u64,
+    cluster_event_count: u64,
+}
+
+impl AdaptiveCapacityPolicy {
+    /// The smallest capacity proposed by the default policy.
+    pub const DEFAULT_MIN_CAPACITY: u64 = 8;
+    /// The largest capacity proposed by the default policy.
+    pub const DEFAULT_MAX_CAPACITY: u64 = 2048;
+    /// The count of generated IDs the default policy observes before proposing a change.
+    pub const DEFAULT_MIN_SAMPLE_COUNT: u64 = 1024;
+
+    /// Instantiates a policy that proposes capacities between `min_capacity` and `max_capacity` (inclusive) once at least
+    /// `min_sample_count` generated IDs have been observed.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The minimum capacity must be a non-zero integer no greater than the maximum capacity.
+    pub fn new(
+        min_capacity: u64,
+        max_capacity: u64,
+        min_sample_count: u64,
+    ) -> Result<Self, AllocatorError> {
+        if min_capacity < 1 || min_capacity > max_capacity {
+            return Err(AllocatorError::InvalidClusterCapacity);
+        }
+        Ok(AdaptiveCapacityPolicy {
+            min_capacity,
+            max_capacity,
+            min_sample_count,
+            id_count: 0,
+            cluster_event_count: 0,
+        })
+    }
+}
+
+impl Default for AdaptiveCapacityPolicy {
+    fn default() -> Self {
+        AdaptiveCapacityPolicy {
+            min_capacity: Self::DEFAULT_MIN_CAPACITY,
+            max_capacity: Self::DEFAULT_MAX_CAPACITY,
+            min_sample_count: Self::DEFAULT_MIN_SAMPLE_COUNT,
+            id_count: 0,
+            cluster_event_count: 0,
+        }
+    }
+}
+
+impl ClusterCapacityPolicy for AdaptiveCapacityPolicy {
+    fn propose_capacity(&mut self, current_capacity: u64, stats: &TelemetryStats) -> Option<u64> {
+        self.id_count = self
+            .id_count
+            .saturating_add(stats.eager_final_count)
+            .saturating_add(stats.local_id_count);
+        self.cluster_event_count = self
+            .cluster_event_count
+            .saturating_add(stats.local_expansion_count)
+            .saturating_add(stats.local_cluster_creation_count);
+        if self.id_count < self.min_sample_count {
+            return None;
+        }
+        let ids_per_event = match self.cluster_event_count {
+            // Every ID fit within existing clusters.
+            0 => None,
+            event_count => Some(self.id_count / event_count),
+        };
+        self.id_count = 0;
+        self.cluster_event_count = 0;
+        let ids_per_event = ids_per_event?;
+        if ids_per_event <= current_capacity && ids_per_event >= current_capacity / 4 {
+            return None;
+        }
+        let proposed_capacity = ids_per_event
+            .checked_next_power_of_two()
+            .unwrap_or(u64::MAX)
+            .clamp(self.min_capacity, self.max_capacity);
+        if proposed_capacity == current_capacity {
+            None
+        } else {
+            Some(proposed_capacity)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(id_count: u64, cluster_event_count: u64) -> TelemetryStats {
+        TelemetryStats {
+            eager_final_count: id_count / 2,
+            local_id_count: id_count - id_count / 2,
+            expansion_count: 0,
+            cluster_creation_count: 0,
+            local_expansion_count: cluster_event_count / 2,
+            local_cluster_creation_count: cluster_event_count - cluster_event_count / 2,
+        }
+    }
+
+    #[test]
+    fn test_invalid_bounds() {
+        assert!(AdaptiveCapacityPolicy::new(0, 10, 0).is_err());
+        assert!(AdaptiveCapacityPolicy::new(11, 10, 0).is_err());
+        assert!(AdaptiveCapacityPolicy::new(10, 10, 0).is_ok());
+    }
+
+    #[test]
+    fn test_waits_for_sample() {
+        let mut policy = AdaptiveCapacityPolicy::new(1, 1024, 100).unwrap();
+        assert_eq!(policy.propose_capacity(4, &stats(60, 6)), None);
+        assert_eq!(policy.propose_capacity(4, &stats(60, 6)), Some(16));
+        // Statistics are reset after each proposal
+        assert_eq!(policy.propose_capacity(4, &stats(60, 6)), None);
+    }
+
+    #[test]
+    fn test_grows_and_shrinks() {
+        let mut policy = AdaptiveCapacityPolicy::default();
+        assert_eq!(policy.propose_capacity(512, &stats(2000, 2)), Some(1024));
+        assert_eq!(policy.propose_capacity(512, &stats(2000, 1)), Some(2048));
+        assert_eq!(policy.propose_capacity(512, &stats(2000, 20)), Some(128));
+        assert_eq!(policy.propose_capacity(512, &stats(2000, 2000)), Some(8));
+        // Within bounds of the current capacity
+        assert_eq!(policy.propose_capacity(512, &stats(2000, 5)), None);
+        assert_eq!(policy.propose_capacity(512, &stats(2000, 0)), None);
+        // Clamped proposals that match the current capacity are not changes
+        assert_eq!(policy.propose_capacity(2048, &stats(20000, 1)), None);
+    }
+}
@@ -0,0 +1,534 @@
+use super::persistence::{DEFAULT_CLUSTER_CAPACITY, DEFAULT_MAX_CAPACITY_HINT};
+use super::tables::final_space::FinalSpace;
+use super::tables::session_space::{ClusterRef, IdCluster, SessionSpaceRef, Sessions};
+use super::{
+    BatchFinalizationError, CapacityChange, ClusterEvent, ClusterSnapshot, FinalizationOutcome,
+    FinalizedSpan, IdRange, PlannedFinalization, RangePlacement, SessionSpaceReport, SpaceReport,
+};
+use id_types::final_id::{final_id_from_id, get_id_from_final_id};
+use id_types::*;
+
+/// The finalized state of a document: all sessions and their clusters, in both session space and final space.
+/// Shared by the client `IdCompressor` and the finalize-only `SequencerCompressor`.
+#[derive(Debug)]
+pub struct FinalizedState {
+    // Cache of one greater than the largest finalized final ID in final space. Used to optimize normalization.
+    pub(crate) final_id_limit: FinalId,
+    // The current value for sizing of new clusters.
+    pub(crate) cluster_capacity: u64,
+    // (final ID limit when the change was applied, new capacity) for every sequenced change to the cluster capacity (see
+    // `apply_capacity_change`), in order. Unsequenced changes made via `set_cluster_capacity` are not recorded.
+    pub(crate) capacity_history: Vec<(FinalId, u64)>,
+    // The largest cluster capacity a range may request via its capacity hint.
+    pub(crate) max_capacity_hint: u64,
+    // The exclusive upper bound on final IDs reserved by clusters. Not persisted.
+    pub(crate) final_id_ceiling: u64,
+    pub(crate) sessions: Sessions,
+    pub(crate) final_space: FinalSpace,
+}
+
+impl FinalizedState {
+    pub fn new() -> Self {
+        FinalizedState {
+            final_id_limit: final_id_from_id(0),
+            cluster_capacity: DEFAULT_CLUSTER_CAPACITY,
+            capacity_history: Vec::new(),
+            max_capacity_hint: DEFAULT_MAX_CAPACITY_HINT,
+            final_id_ceiling: u64::MAX,
+            sessions: Sessions::new(),
+            final_space: FinalSpace::new(),
+        }
+    }
+
+    pub fn set_cluster_capacity(
+        &mut self,
+        new_cluster_capacity: u64,
+    ) -> Result<(), AllocatorError> {
+        if new_cluster_capacity < 1 {
+            Err(AllocatorError::InvalidClusterCapacity)
+        } else {
+            self.cluster_capacity = new_cluster_capacity;
+            Ok(())
+        }
+    }
+
+    pub fn propose_capacity_change(
+        &self,
+        new_cluster_capacity: u64,
+    ) -> Result<CapacityChange, AllocatorError> {
+        if new_cluster_capacity < 1 {
+            return Err(AllocatorError::InvalidClusterCapacity);
+        }
+        Ok(CapacityChange {
+            previous_capacity: self.cluster_capacity,
+            new_capacity: new_cluster_capacity,
+        })
+    }
+
+    pub fn apply_capacity_change(&mut self, change: &CapacityChange) -> Result<(), AllocatorError> {
+        if change.previous_capacity != self.cluster_capacity {
+            return Err(AllocatorError::CapacityChangeDiverged);
+        }
+        let previous_capacity = self.cluster_capacity;
+        self.set_cluster_capacity(change.new_capacity)?;
+        if change.new_capacity != previous_capacity {
+            self.capacity_history
+                .push((self.final_id_limit, change.new_capacity));
+        }
+        Ok(())
+    }
+
+    pub fn set_max_capacity_hint(&mut self, max_capacity_hint: u64) -> Result<(), AllocatorError> {
+        if max_capacity_hint < 1 {
+            Err(AllocatorError::InvalidClusterCapacity)
+        } else {
+            self.max_capacity_hint = max_capacity_hint;
+            Ok(())
+        }
+    }
+
+    // Returns the capacity with which clusters are created or expanded for a range with the supplied capacity hint.
+    fn get_range_cluster_capacity(&self, capacity_hint: Option<u64>) -> u64 {
+        match capacity_hint {
+            None => self.cluster_capacity,
+            Some(capacity_hint) => capacity_hint.min(self.max_capacity_hint),
+        }
+    }
+
+    // Determines how the supplied range of IDs would be finalized, without modifying any state.
+    pub fn plan_finalization(
+        &self,
+        id_range: &IdRange,
+    ) -> Result<PlannedFinalization, AllocatorError> {
+        let planned = self.plan_range_placement(id_range)?;
+        match planned.placement {
+            RangePlacement::CreatesFirstCluster(_, claimed_count)
+            | RangePlacement::ExpandsTailCluster(_, claimed_count)
+            | RangePlacement::OverflowsToNewCluster(_, claimed_count) => {
+                // Clusters are only ever created or expanded at the end of final space.
+                self.check_final_id_ceiling(claimed_count)?;
+            }
+            RangePlacement::Empty | RangePlacement::FitsTailCluster => {}
+        }
+        Ok(planned)
+    }
+
+    // Checks that `claimed_count` more final IDs can be reserved at the end of final space without exceeding the ceiling.
+    pub fn check_final_id_ceiling(&self, claimed_count: u64) -> Result<(), AllocatorError> {
+        match get_id_from_final_id(self.get_next_base_final()).checked_add(claimed_count) {
+            Some(final_id_limit) if final_id_limit <= self.final_id_ceiling => Ok(()),
+            _ => Err(AllocatorError::FinalIdSpaceExhausted),
+        }
+    }
+
+    fn plan_range_placement(
+        &self,
+        &IdRange {
+            id: session_id,
+            range,
+            capacity_hint,
+        }: &IdRange,
+    ) -> Result<PlannedFinalization, AllocatorError> {
+        if capacity_hint == Some(0) {
+            return Err(AllocatorError::MalformedIdRange);
+        }
+        // Check if the range has IDs
+        let (range_base_gen_count, range_len) = match range {
+            None => {
+                return Ok(PlannedFinalization {
+                    placement: RangePlacement::Empty,
+                    spans: Vec::new(),
+                });
+            }
+            Some((_, 0)) => {
+                return Err(AllocatorError::MalformedIdRange);
+            }
+            Some(range) => range,
+        };
+
+        let range_base_local = i64::try_from(range_base_gen_count)
+            .ok()
+            .and_then(|generation_count| LocalId::try_from(-generation_count).ok())
+            .ok_or(AllocatorError::MalformedIdRange)?;
+        let range_base_stable = StableId::from(session_id)
+            .checked_add(range_base_gen_count - 1)
+            .ok_or(AllocatorError::StableIdOverflow)?;
+        let cluster_capacity = self.get_range_cluster_capacity(capacity_hint);
+        let range_max_stable = range_base_stable
+            .checked_add(range_len)
+            .and_then(|range_end| range_end.checked_add(cluster_capacity))
+            .ok_or(AllocatorError::StableIdOverflow)?;
+        // Checks collision for the maximum new cluster span (the condition in which the current tail cluster is exactly full)
+        if self
+            .sessions
+            .range_collides(session_id, range_base_stable, range_max_stable)
+        {
+            return Err(AllocatorError::ClusterCollision);
+        }
+        let tail_cluster = match self
+            .sessions
+            .get(session_id)
+            .and_then(|&session_space_ref| {
+                self.sessions
+                    .deref_session_space(session_space_ref)
+                    .get_tail_cluster()
+            }) {
+            None => {
+                // This is the first cluster in the session space
+                if range_base_local != -1 {
+                    return Err(AllocatorError::RangeFinalizedOutOfOrder);
+                }
+                let base_final = self.get_next_base_final();
+                return Ok(PlannedFinalization {
+                    placement: RangePlacement::CreatesFirstCluster(
+                        base_final,
+                        cluster_capacity + range_len,
+                    ),
+                    spans: vec![FinalizedSpan {
+                        base_local: range_base_local,
+                        base_final,
+                        count: range_len,
+                    }],
+                });
+            }
+            Some(tail_cluster) => tail_cluster,
+        };
+        let last_cluster_base_final = self
+            .final_space
+            .get_tail_cluster(&self.sessions)
+            .unwrap()
+            .base_final_id;
+        // Saturates so that an overfilled cluster (which only an unvalidated snapshot can contain) has no remaining capacity
+        let remaining_capacity = tail_cluster.capacity.saturating_sub(tail_cluster.count);
+        if tail_cluster.base_local_id - tail_cluster.count != range_base_local {
+            return Err(AllocatorError::RangeFinalizedOutOfOrder);
+        }
+        let range_base_final = tail_cluster.base_final_id + tail_cluster.count;
+        if remaining_capacity >= range_len {
+            // The current range fits in the existing cluster
+            return Ok(PlannedFinalization {
+                placement: RangePlacement::FitsTailCluster,
+                spans: vec![FinalizedSpan {
+                    base_local: range_base_local,
+                    base_final: range_base_final,
+                    count: range_len,
+                }],
+            });
+        }
+        let overflow = range_len - remaining_capacity;
+        let new_claimed_final_count = overflow + cluster_capacity;
+        if tail_cluster.base_final_id == last_cluster_base_final {
+            // Tail_cluster is the last cluster, and so can be expanded.
+            Ok(PlannedFinalization {
+                placement: RangePlacement::ExpandsTailCluster(
+                    tail_cluster.base_final_id,
+                    new_claimed_final_count,
+                ),
+                spans: vec![FinalizedSpan {
+                    base_local: range_base_local,
+                    base_final: range_base_final,
+                    count: range_len,
+                }],
+            })
+        } else {
+            // Tail cluster is not the last cluster. Fill and overflow to new.
+            let new_cluster_base_final = self.get_next_base_final();
+            let mut spans = Vec::with_capacity(2);
+            if remaining_capacity > 0 {
+                spans.push(FinalizedSpan {
+                    base_local: range_base_local,
+                    base_final: range_base_final,
+                    count: remaining_capacity,
+                });
+            }
+            spans.push(FinalizedSpan {
+                base_local: range_base_local - remaining_capacity,
+                base_final: new_cluster_base_final,
+                count: overflow,
+            });
+            Ok(PlannedFinalization {
+                placement: RangePlacement::OverflowsToNewCluster(
+                    new_cluster_base_final,
+                    new_claimed_final_count,
+                ),
+                spans,
+            })
+        }
+    }
+
+    // Finalizes the supplied range of IDs, returning the spans of IDs finalized and the clusters created or expanded.
+    pub fn finalize_range(
+        &mut self,
+        id_range: &IdRange,
+    ) -> Result<FinalizationOutcome, AllocatorError> {
+        let PlannedFinalization { placement, spans } = self.plan_finalization(id_range)?;
+        let mut cluster_events = Vec::new();
+        match placement {
+            RangePlacement::Empty => {}
+            RangePlacement::CreatesFirstCluster(base_final, capacity) => {
+                let session_space_ref = self.sessions.get_or_create(id_range.id);
+                let new_cluster_ref =
+                    self.add_empty_cluster(session_space_ref, spans[0].base_local, capacity)?;
+                self.sessions.deref_cluster_mut(new_cluster_ref).count += spans[0].count;
+                cluster_events.push(ClusterEvent::Created(base_final, capacity));
+            }
+            RangePlacement::FitsTailCluster => {
+                self.get_tail_cluster_mut(id_range.id).count += spans[0].count;
+            }
+            RangePlacement::ExpandsTailCluster(base_final, added_capacity) => {
+                let tail_cluster = self.get_tail_cluster_mut(id_range.id);
+                tail_cluster.capacity += added_capacity;
+                tail_cluster.count += spans[0].count;
+                cluster_events.push(ClusterEvent::Expanded(base_final, added_capacity));
+            }
+            RangePlacement::OverflowsToNewCluster(base_final, capacity) => {
+                let tail_cluster = self.get_tail_cluster_mut(id_range.id);
+                tail_cluster.count = tail_cluster.capacity;
+                let overflow_span = spans[spans.len() - 1];
+                let session_space_ref = *self.sessions.get(id_range.id).unwrap();
+                let new_cluster_ref =
+                    self.add_empty_cluster(session_space_ref, overflow_span.base_local, capacity)?;
+                self.sessions.deref_cluster_mut(new_cluster_ref).count += overflow_span.count;
+                cluster_events.push(ClusterEvent::Created(base_final, capacity));
+            }
+        }
+        if let Some((range_base_gen_count, _)) = id_range.range {
+            let session_space_ref = *self.sessions.get(id_range.id).unwrap();
+            self.sessions
+                .deref_session_space_mut(session_space_ref)
+                .push_range_base(range_base_gen_count);
+        }
+        self.refresh_final_id_limit();
+        Ok(FinalizationOutcome {
+            spans,
+            cluster_events,
+        })
+    }
+
+    fn get_tail_cluster_mut(&mut self, session_id: SessionId) -> &mut IdCluster {
+        let session_space_ref = *self.sessions.get(session_id).unwrap();
+        self.sessions
+            .deref_session_space_mut(session_space_ref)
+            .get_tail_cluster_mut()
+            .unwrap()
+    }
+
+    // Finalizes the supplied ranges in order. If any range fails to finalize, all ranges before it are rolled back
+    // and the finalized state is left unchanged.
+    pub fn finalize_ranges(
+        &mut self,
+        id_ranges: &[IdRange],
+    ) -> Result<Vec<FinalizationOutcome>, BatchFinalizationError> {
+        let session_count = self.sessions.get_session_count();
+        let final_id_limit = self.final_id_limit;
+        let mut outcomes = Vec::with_capacity(id_ranges.len());
+        for (index, id_range) in id_ranges.iter().enumerate() {
+            match self.finalize_range(id_range) {
+                Ok(outcome) => outcomes.push(outcome),
+                Err(error) => {
+                    for outcome in outcomes.iter().rev() {
+                        self.undo_finalization(outcome);
+                    }
+                    // Failed finalization may create an empty session space, so truncation follows any rollback.
+                    self.sessions.truncate(session_count);
+                    self.final_id_limit = final_id_limit;
+                    return Err(BatchFinalizationError { index, error });
+                }
+            }
+        }
+        Ok(outcomes)
+    }
+
+    // Reverts the effects of the most recently applied finalization.
+    fn undo_finalization(&mut self, outcome: &FinalizationOutcome) {
+        if let Some(span) = outcome.spans.first() {
+            let cluster_ref = self
+                .final_space
+                .search(span.base_final, &self.sessions)
+                .unwrap();
+            _ = self
+                .sessions
+                .deref_session_space_mut(cluster_ref.get_session_space_ref())
+                .pop_range_base();
+        }
+        for span in outcome.spans.iter().rev() {
+            let cluster_ref = self
+                .final_space
+                .search(span.base_final, &self.sessions)
+                .unwrap();
+            self.sessions.deref_cluster_mut(cluster_ref).count -= span.count;
+        }
+        for event in outcome.cluster_events.iter().rev() {
+            match *event {
+                ClusterEvent::Created(..) => {
+                    let cluster_ref = self.final_space.pop_cluster().unwrap();
+                    _ = self
+                        .sessions
+                        .deref_session_space_mut(cluster_ref.get_session_space_ref())
+                        .pop_cluster();
+                }
+                ClusterEvent::Expanded(base_final, added_capacity) => {
+                    let cluster_ref = self.final_space.search(base_final, &self.sessions).unwrap();
+                    self.sessions.deref_cluster_mut(cluster_ref).capacity -= added_capacity;
+                }
+            }
+        }
+    }
+
+    // Returns true if the range matches (in base and length) a range already finalized by the session, and an error if only
+    // some of its IDs have been finalized. Other ranges lying within the session's finalized IDs are not replays, and are
+    // left for finalization to reject as out of order.
+    pub fn is_range_finalized(
+        &self,
+        &IdRange {
+            id: session_id,
+            range,
+            ..
+        }: &IdRange,
+    ) -> Result<bool, AllocatorError> {
+        let (range_base_gen_count, range_last_gen_count) = match range {
+            None => return Ok(false),
+            Some((0, _)) | Some((_, 0)) => return Err(AllocatorError::MalformedIdRange),
+            Some((range_base_gen_count, range_len)) => (
+                range_base_gen_count,
+                (range_base_gen_count - 1)
+                    .checked_add(range_len)
+                    .ok_or(AllocatorError::MalformedIdRange)?,
+            ),
+        };
+        let session_space = match self.sessions.get(session_id) {
+            None => return Ok(false),
+            Some(&session_space_ref) => self.sessions.deref_session_space(session_space_ref),
+        };
+        let finalized_count = session_space.get_finalized_count();
+        if range_base_gen_count > finalized_count {
+            Ok(false)
+        } else if range_last_gen_count <= finalized_count {
+            Ok(session_space.get_range_end(range_base_gen_count) == Some(range_last_gen_count + 1))
+        } else {
+            Err(AllocatorError::RangePartiallyFinalized)
+        }
+    }
+
+    pub fn add_empty_cluster(
+        &mut self,
+        session_space_ref: SessionSpaceRef,
+        base_local: LocalId,
+        capacity: u64,
+    ) -> Result<ClusterRef, AllocatorError> {
+        self.check_final_id_ceiling(capacity)?;
+        let next_base_final = self.get_next_base_final();
+        let session_space = self.sessions.deref_session_space_mut(session_space_ref);
+        let new_cluster_ref = session_space.add_empty_cluster(
+            session_space_ref,
+            next_base_final,
+            base_local,
+            capacity,
+        );
+        self.final_space
+            .add_cluster(new_cluster_ref, &self.sessions);
+
+        Ok(new_cluster_ref)
+    }
+
+    // Returns a snapshot of every cluster, in final space order.
+    pub fn get_cluster_layout(&self) -> Vec<ClusterSnapshot> {
+        self.final_space
+            .get_clusters(&self.sessions)
+            .map(|(cluster, cluster_ref)| ClusterSnapshot {
+                session_id: self
+                    .sessions
+                    .get_session_id(cluster_ref.get_session_space_ref()),
+                base_final_id: cluster.base_final_id,
+                base_local_id: cluster.base_local_id,
+                capacity: cluster.capacity,
+                count: cluster.count,
+            })
+            .collect()
+    }
+
+    // Summarizes the capacity and count of every session's clusters.
+    pub fn space_report(&self) -> SpaceReport {
+        let sessions: Vec<SessionSpaceReport> = self
+            .sessions
+            .iter_in_stable_order()
+            .filter(|(_, session_space)| !session_space.cluster_chain_is_empty())
+            .map(|(session_id, session_space)| {
+                let mut report = SessionSpaceReport {
+                    session_id,
+                    cluster_count: 0,
+                    capacity: 0,
+                    count: 0,
+                    unused_count: 0,
+                };
+                for cluster in session_space.get_clusters() {
+                    report.cluster_count += 1;
+                    report.capacity += cluster.capacity;
+                    report.count += cluster.count;
+                    report.unused_count += cluster.capacity.saturating_sub(cluster.count);
+                }
+                report
+            })
+            .collect();
+        SpaceReport {
+            cluster_count: sessions.iter().map(|report| report.cluster_count).sum(),
+            capacity: sessions.iter().map(|report| report.capacity).sum(),
+            count: sessions.iter().map(|report| report.count).sum(),
+            unused_count: sessions.iter().map(|report| report.unused_count).sum(),
+            sessions,
+        }
+    }
+
+    // Returns the base final ID of the next cluster to be created.
+    fn get_next_base_final(&self) -> FinalId {
+        match self.final_space.get_tail_cluster(&self.sessions) {
+            Some(cluster) => cluster.base_final_id + cluster.capacity,
+            None => final_id_from_id(0),
+        }
+    }
+
+    // Returns the final form of a session's local ID, if it has been finalized.
+    pub fn try_convert_finalized_local(
+        &self,
+        session_space_ref: SessionSpaceRef,
+        local_id: LocalId,
+    ) -> Option<FinalId> {
+        self.sessions
+            .deref_session_space(session_space_ref)
+            .try_convert_to_final(local_id, false)
+    }
+
+    // Returns the cluster containing the final ID, if it has been finalized.
+    pub fn get_finalized_cluster(&self, final_id: FinalId) -> Option<ClusterRef> {
+        if final_id >= self.final_id_limit {
+            return None;
+        }
+        let cluster_ref = self.final_space.search(final_id, &self.sessions)?;
+        let cluster = self.sessions.deref_cluster(cluster_ref);
+        if final_id - cluster.base_final_id >= cluster.count as i64 {
+            return None;
+        }
+        Some(cluster_ref)
+    }
+
+    // Updates the final ID limit to reflect the finalized count of the last cluster in final space.
+    pub fn refresh_final_id_limit(&mut self) {
+        if let Some(cluster) = self.final_space.get_tail_cluster(&self.sessions) {
+            self.final_id_limit = cluster.base_final_id + cluster.count;
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    pub(crate) fn equals_test_only(&self, other: &FinalizedState) -> bool {
+        self.final_id_limit == other.final_id_limit
+            && self.sessions.equals_test_only(&other.sessions)
+            && self.final_space.equals_test_only(
+                &other.final_space,
+                &self.sessions,
+                &other.sessions,
+            )
+            && self.cluster_capacity == other.cluster_capacity
+            && self.capacity_history == other.capacity_history
+            && self.max_capacity_hint == other.max_capacity_hint
+    }
+}
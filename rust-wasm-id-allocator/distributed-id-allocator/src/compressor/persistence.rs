@@ -1,7 +1,17 @@
-use super::{persistence_utils::Deserializer, IdCompressor};
-use id_types::{errors::ErrorString, SessionId};
+use super::{
+    finalized_state::FinalizedState, persistence_utils::Deserializer,
+    tables::session_space::IdCluster, tables::session_space::SessionSpaceRef,
+    tables::session_space_normalizer::SessionSpaceNormalizer, IdCompressor, ValidationReport,
+};
+use id_types::{
+    errors::ErrorString,
+    final_id::{final_id_from_id, get_id_from_final_id},
+    local_id::local_id_from_id,
+    SessionId,
+};
 
 pub(super) const DEFAULT_CLUSTER_CAPACITY: u64 = 512;
+pub(super) const DEFAULT_MAX_CAPACITY_HINT: u64 = 1 << 16;
 
 pub fn deserialize<FMakeSession>(
     bytes: &[u8],
@@ -10,19 +20,215 @@ pub fn deserialize<FMakeSession>(
 where
     FMakeSession: FnOnce() -> SessionId,
 {
+    let mut persisted = read(bytes)?;
+    let mut pending_ranges = None;
+    let mut compressor = match persisted.local_state.take() {
+        None => {
+            let compressor = IdCompressor::new_with_session_id(make_session_id());
+            if persisted.session_ids.contains(&compressor.session_id) {
+                return Err(DeserializationError::InvalidResumedSession);
+            }
+            compressor
+        }
+        Some(local_state) => {
+            let mut compressor = IdCompressor::new_with_session_id(local_state.session_id);
+            compressor.generated_id_count = local_state.generated_id_count;
+            compressor.next_range_base_generation_count =
+                local_state.next_range_base_generation_count;
+            compressor.session_space_normalizer = local_state.session_space_normalizer;
+            pending_ranges = local_state.pending_ranges;
+            compressor
+        }
+    };
+    populate_finalized(&mut compressor.finalized, &persisted)?;
+    compressor.pending_ranges = match pending_ranges {
+        Some(pending_ranges) => pending_ranges,
+        None => {
+            // Formats predating pending ranges are treated as having taken all unfinalized IDs in a single range.
+            compressor.pending_ranges = match compressor.next_range_base_generation_count {
+                0 | 1 => Vec::new(),
+                next_range_base => vec![(1, next_range_base - 1, None)],
+            };
+            compressor.prune_pending_ranges();
+            compressor.pending_ranges
+        }
+    };
+    Ok(compressor)
+}
+
+// Deserializes only the finalized state, discarding any local state.
+pub fn deserialize_finalized(bytes: &[u8]) -> Result<FinalizedState, DeserializationError> {
+    let persisted = read(bytes)?;
+    let mut finalized = FinalizedState::new();
+    populate_finalized(&mut finalized, &persisted)?;
+    Ok(finalized)
+}
+
+fn read(bytes: &[u8]) -> Result<PersistedState, DeserializationError> {
     let mut deserializer = Deserializer::new(bytes);
-    let version = deserializer.take_u32();
-    match version {
-        1 => v1::deserialize(&mut deserializer, make_session_id),
+    let version = deserializer.take_u32()?;
+    let persisted = match version {
+        1 => v1::deserialize(&mut deserializer),
+        2 => v2::deserialize(&mut deserializer),
+        3 => v3::deserialize(bytes, &mut deserializer),
+        4 => v4::deserialize(bytes, &mut deserializer),
+        5 => v5::deserialize(bytes, &mut deserializer),
+        6 => v6::deserialize(bytes, &mut deserializer),
+        7 => v7::deserialize(bytes, &mut deserializer),
+        8 => v8::deserialize(bytes, &mut deserializer),
         _ => Err(DeserializationError::UnknownVersion),
+    }?;
+    if !deserializer.is_empty() {
+        return Err(DeserializationError::MalformedInput);
+    }
+    Ok(persisted)
+}
+
+// The contents of serialized state, independent of format version.
+struct PersistedState {
+    local_state: Option<PersistedLocalState>,
+    cluster_capacity: u64,
+    session_ids: Vec<SessionId>,
+    // (session index, capacity, count), in final space order.
+    clusters: Vec<(usize, u64, u64)>,
+    // (final ID limit when the change was applied, new capacity), in the order the changes were applied.
+    capacity_history: Vec<(u64, u64)>,
+    max_capacity_hint: u64,
+    // The range bases of each session, in session order, or empty if the format predates their persistence.
+    range_bases: Vec<Vec<u64>>,
+}
+
+struct PersistedLocalState {
+    session_id: SessionId,
+    generated_id_count: u64,
+    next_range_base_generation_count: u64,
+    session_space_normalizer: SessionSpaceNormalizer,
+    // None if the format predates the persistence of pending ranges. Capacity hints are None if the format predates
+    // their persistence.
+    pending_ranges: Option<Vec<(u64, u64, Option<u64>)>>,
+}
+
+// Reads a session index from a cluster table, checking that it refers to a serialized session.
+fn check_session_index(
+    session_index: u64,
+    session_ids: &[SessionId],
+) -> Result<usize, DeserializationError> {
+    match usize::try_from(session_index) {
+        Ok(session_index) if session_index < session_ids.len() => Ok(session_index),
+        _ => Err(DeserializationError::MalformedInput),
+    }
+}
+
+fn populate_finalized(
+    finalized: &mut FinalizedState,
+    persisted: &PersistedState,
+) -> Result<(), DeserializationError> {
+    finalized.cluster_capacity = persisted.cluster_capacity;
+    let session_refs: Vec<SessionSpaceRef> = persisted
+        .session_ids
+        .iter()
+        .map(|session_id| finalized.sessions.get_or_create(*session_id))
+        .collect();
+    for &(session_index, capacity, count) in &persisted.clusters {
+        add_deserialized_cluster(finalized, session_refs[session_index], capacity, count)?;
+    }
+    for (&session_space_ref, range_bases) in session_refs.iter().zip(&persisted.range_bases) {
+        let session_space = finalized
+            .sessions
+            .deref_session_space_mut(session_space_ref);
+        // Every recorded range must contain at least one finalized ID.
+        if let Some(&last_range_base) = range_bases.last() {
+            if last_range_base > session_space.get_finalized_count() {
+                return Err(DeserializationError::MalformedInput);
+            }
+        }
+        for &range_base in range_bases {
+            session_space.push_range_base(range_base);
+        }
+    }
+    finalized.refresh_final_id_limit();
+    finalized.capacity_history = persisted
+        .capacity_history
+        .iter()
+        .map(|&(final_id_limit, capacity)| (final_id_from_id(final_id_limit), capacity))
+        .collect();
+    finalized.max_capacity_hint = persisted.max_capacity_hint;
+    Ok(())
+}
+
+// The only empty session (if there is one) will be the local session.
+// It is stored first in the session vector, so to avoid accumulating empty
+// sessions in the serialized state we must omit it by slicing off the first
+// session id, reducing the session count by 1, and adjusting all cluster
+// session indexes by 1.
+fn get_session_count_delta(compressor: &IdCompressor) -> usize {
+    if compressor.generated_id_count == 0 {
+        1
+    } else {
+        0
+    }
+}
+
+// Appends a deserialized cluster to the tail of its session's cluster chain and of final space.
+fn add_deserialized_cluster(
+    finalized: &mut FinalizedState,
+    session_space_ref: SessionSpaceRef,
+    capacity: u64,
+    count: u64,
+) -> Result<(), DeserializationError> {
+    let base_final_id = match finalized.final_space.get_tail_cluster(&finalized.sessions) {
+        Some(cluster) => cluster.base_final_id + cluster.capacity,
+        None => final_id_from_id(0),
+    };
+    let session_space = finalized.sessions.deref_session_space(session_space_ref);
+    let base_local_id = match session_space.get_tail_cluster() {
+        Some(cluster) => cluster.base_local_id - cluster.capacity,
+        None => local_id_from_id(-1),
+    };
+    // Empty or overfilled clusters would overlap the clusters following them in final space.
+    if capacity == 0 || count > capacity {
+        return Err(DeserializationError::MalformedInput);
+    }
+    // Reject clusters whose ID ranges would overflow either ID space.
+    let final_overflow = get_id_from_final_id(base_final_id)
+        .checked_add(capacity)
+        .is_none();
+    let local_overflow = match base_local_id.to_generation_count().checked_add(capacity) {
+        Some(next_generation_count) => next_generation_count > i64::MAX as u64,
+        None => true,
+    };
+    if final_overflow || local_overflow {
+        return Err(DeserializationError::MalformedInput);
     }
+    let new_cluster = IdCluster {
+        base_final_id,
+        base_local_id,
+        capacity,
+        count,
+    };
+    let new_cluster_ref = finalized
+        .sessions
+        .deref_session_space_mut(session_space_ref)
+        .add_cluster(session_space_ref, new_cluster);
+    finalized
+        .final_space
+        .add_cluster(new_cluster_ref, &finalized.sessions);
+    Ok(())
 }
 
 #[derive(Debug, PartialEq, Eq)]
+/// Enumerated error variants for compressor deserialization.
 pub enum DeserializationError {
+    /// The serialized state was produced by the session it is being resumed as.
     InvalidResumedSession,
+    /// The serialized state has an unrecognized version.
     UnknownVersion,
+    /// The serialized state is truncated or otherwise unreadable.
     MalformedInput,
+    /// The serialized state was readable but violates the compressor's structural invariants.
+    FailedValidation(ValidationReport),
+    /// The serialized state does not match its checksum, and has likely been corrupted.
+    ChecksumMismatch,
 }
 
 impl ErrorString for DeserializationError {
@@ -32,35 +238,42 @@ impl ErrorString for DeserializationError {
             DeserializationError::InvalidResumedSession => "Cannot resume existing session.",
             DeserializationError::MalformedInput => "Malformed serialized input.",
             DeserializationError::UnknownVersion => "Unknown deserialization error.",
+            DeserializationError::FailedValidation(_) => "Serialized state failed validation.",
+            DeserializationError::ChecksumMismatch => {
+                "Serialized state failed checksum verification."
+            }
         }
     }
 }
 
 pub mod v1 {
-    use super::DeserializationError;
-    use crate::{
-        compressor::IdCompressor,
-        compressor::{
-            persistence_utils::{
-                write_u128_to_vec, write_u32_to_vec, write_u64_to_vec, Deserializer,
-            },
+    use super::{
+        check_session_index, DeserializationError, PersistedLocalState, PersistedState,
+        DEFAULT_MAX_CAPACITY_HINT,
+    };
+    use crate::compressor::{
+        persistence_utils::Deserializer,
+        tables::session_space_normalizer::persistence::v1::deserialize_normalizer,
+    };
+    use id_types::session_id::{session_id_from_id_u128, session_id_from_uuid_u128};
+    #[cfg(test)]
+    use {
+        super::get_session_count_delta,
+        crate::compressor::IdCompressor,
+        crate::compressor::{
+            persistence_utils::{write_u128_to_vec, write_u32_to_vec, write_u64_to_vec},
             tables::{
                 session_space::IdCluster,
-                session_space_normalizer::persistence::v1::{
-                    deserialize_normalizer, serialize_normalizer,
-                },
+                session_space_normalizer::persistence::v1::serialize_normalizer,
             },
         },
+        id_types::SessionId,
+        id_types::{final_id::get_id_from_final_id, StableId},
+        std::mem::size_of,
     };
-    use id_types::{
-        final_id::{final_id_from_id, get_id_from_final_id},
-        local_id::local_id_from_id,
-        session_id::{session_id_from_id_u128, session_id_from_uuid_u128},
-        SessionId, StableId,
-    };
-    use std::mem::size_of;
 
     // Layout
+    // Superseded by v2, and only written in tests to verify that existing documents remain readable.
     // version: u32
     // has_local_state: bool as u32
     // clusters_are_32_bit: bool as u32
@@ -73,6 +286,7 @@ pub mod v1 {
     // session_uuid_u128s: u128[],
     // cluster_data: (session_index: u64, capacity: u64, count: u64)[],
 
+    #[cfg(test)]
     pub fn serialize(compressor: &IdCompressor) -> Vec<u8> {
         let mut bytes: Vec<u8> = Vec::new();
         let is_32_bit = serialize_header(compressor, false, &mut bytes);
@@ -80,6 +294,7 @@ pub mod v1 {
         bytes
     }
 
+    #[cfg(test)]
     pub fn serialize_with_local(compressor: &IdCompressor) -> Vec<u8> {
         let mut bytes: Vec<u8> = Vec::new();
         let is_32_bit = serialize_header(compressor, true, &mut bytes);
@@ -91,13 +306,15 @@ pub mod v1 {
         bytes
     }
 
+    #[cfg(test)]
     fn serialize_header(compressor: &IdCompressor, is_local: bool, bytes: &mut Vec<u8>) -> bool {
         // Version
         write_u32_to_vec(bytes, 1);
         write_u32_to_vec(bytes, is_local as u32);
         let is_32_bit = match compressor
+            .finalized
             .final_space
-            .get_tail_cluster(&compressor.sessions)
+            .get_tail_cluster(&compressor.finalized.sessions)
         {
             Some(cluster) => get_id_from_final_id(cluster.max_allocated_final()),
             None => 0,
@@ -106,23 +323,15 @@ pub mod v1 {
         is_32_bit
     }
 
+    #[cfg(test)]
     fn serialize_finalized(compressor: &IdCompressor, is_32_bit: bool, bytes: &mut Vec<u8>) {
-        write_u64_to_vec(bytes, compressor.cluster_capacity);
-        let session_count = compressor.sessions.get_session_count();
-        // The only empty session (if there is one) will be the local session.
-        // It is stored first in the session vector, so to avoid accumulating empty
-        // sessions in the serialized state we must omit it by slicing off the first
-        // session id, reducing the session count by 1, and adjusting all cluster
-        // session indexes by 1.
-        let session_count_delta = if compressor.generated_id_count == 0 {
-            1
-        } else {
-            0
-        };
+        write_u64_to_vec(bytes, compressor.finalized.cluster_capacity);
+        let session_count = compressor.finalized.sessions.get_session_count();
+        let session_count_delta = get_session_count_delta(compressor);
         write_u64_to_vec(bytes, (session_count - session_count_delta) as u64);
 
-        let session_slice =
-            &compressor.sessions.get_session_id_slice()[session_count_delta * size_of::<u128>()..];
+        let session_slice = &compressor.finalized.sessions.get_session_id_slice()
+            [session_count_delta * size_of::<u128>()..];
         bytes.extend_from_slice(session_slice);
 
         let write_cluster: fn(
@@ -143,10 +352,14 @@ pub mod v1 {
             }
         };
 
-        write_u64_to_vec(bytes, compressor.final_space.get_cluster_count() as u64);
+        write_u64_to_vec(
+            bytes,
+            compressor.finalized.final_space.get_cluster_count() as u64,
+        );
         compressor
+            .finalized
             .final_space
-            .get_clusters(&compressor.sessions)
+            .get_clusters(&compressor.finalized.sessions)
             .for_each(|(id_cluster, cluster_ref)| {
                 write_cluster(
                     id_cluster,
@@ -156,89 +369,67 @@ pub mod v1 {
             });
     }
 
-    pub(super) fn deserialize<FMakeSession>(
+    // (session_index, capacity, count)
+    type ClusterReadResult = Result<(u64, u64, u64), DeserializationError>;
+
+    pub(super) fn deserialize(
         deserializer: &mut Deserializer,
-        make_session_id: FMakeSession,
-    ) -> Result<IdCompressor, DeserializationError>
-    where
-        FMakeSession: FnOnce() -> SessionId,
-    {
-        let with_local_state = deserializer.take_u32() != 0;
-        let is_32_bit = deserializer.take_u32() != 0;
-        let mut compressor = match with_local_state {
-            false => IdCompressor::new_with_session_id(make_session_id()),
-            true => {
-                let session_uuid_u128 = deserializer.take_u128();
-                let mut compressor =
-                    IdCompressor::new_with_session_id(session_id_from_uuid_u128(session_uuid_u128));
-                compressor.generated_id_count = deserializer.take_u64();
-                compressor.next_range_base_generation_count = deserializer.take_u64();
-                compressor.session_space_normalizer = deserialize_normalizer(deserializer);
-                compressor
-            }
+    ) -> Result<PersistedState, DeserializationError> {
+        let with_local_state = deserializer.take_u32()? != 0;
+        let is_32_bit = deserializer.take_u32()? != 0;
+        let local_state = match with_local_state {
+            false => None,
+            true => Some(PersistedLocalState {
+                session_id: session_id_from_uuid_u128(deserializer.take_u128()?),
+                generated_id_count: deserializer.take_u64()?,
+                next_range_base_generation_count: deserializer.take_u64()?,
+                session_space_normalizer: deserialize_normalizer(deserializer)?,
+                pending_ranges: None,
+            }),
         };
 
-        compressor.cluster_capacity = deserializer.take_u64();
-        let session_count = deserializer.take_u64();
-        let mut session_ref_remap = Vec::new();
+        let cluster_capacity = deserializer.take_u64()?;
+        let session_count = deserializer.take_u64()?;
+        let mut session_ids = Vec::new();
         for _ in 0..session_count {
-            let session_id = session_id_from_id_u128(deserializer.take_u128());
-            if !with_local_state && session_id == compressor.session_id {
-                return Err(DeserializationError::InvalidResumedSession);
-            }
-            session_ref_remap.push(compressor.sessions.get_or_create(session_id));
+            session_ids.push(session_id_from_id_u128(deserializer.take_u128()?));
         }
 
-        let read_cluster: fn(deserializer: &mut Deserializer) -> (u64, u64, u64) = if is_32_bit {
+        let read_cluster: fn(deserializer: &mut Deserializer) -> ClusterReadResult = if is_32_bit {
             |deser| {
-                (
-                    deser.take_u32() as u64,
-                    deser.take_u32() as u64,
-                    deser.take_u32() as u64,
-                )
+                Ok((
+                    deser.take_u32()? as u64,
+                    deser.take_u32()? as u64,
+                    deser.take_u32()? as u64,
+                ))
             }
         } else {
-            |deser| (deser.take_u64(), deser.take_u64(), deser.take_u64())
+            |deser| Ok((deser.take_u64()?, deser.take_u64()?, deser.take_u64()?))
         };
 
-        let cluster_count = deserializer.take_u64();
-        let mut base_final_id = final_id_from_id(0);
+        let cluster_count = deserializer.take_u64()?;
+        let mut clusters = Vec::new();
         for _ in 0..cluster_count {
-            let (session_index, capacity, count) = read_cluster(deserializer);
-            let session_space_ref = session_ref_remap[session_index as usize];
-            let session_space = compressor.sessions.deref_session_space(session_space_ref);
-            let base_local_id = match session_space.get_tail_cluster() {
-                Some(cluster) => cluster.base_local_id - cluster.capacity,
-                None => local_id_from_id(-1),
-            };
-            let new_cluster = IdCluster {
-                base_final_id,
-                base_local_id,
-                capacity,
-                count,
-            };
-            base_final_id = base_final_id + capacity;
-            let new_cluster_ref = compressor
-                .sessions
-                .deref_session_space_mut(session_space_ref)
-                .add_cluster(session_space_ref, new_cluster);
-            compressor
-                .final_space
-                .add_cluster(new_cluster_ref, &compressor.sessions);
+            let (session_index, capacity, count) = read_cluster(deserializer)?;
+            let session_index = check_session_index(session_index, &session_ids)?;
+            clusters.push((session_index, capacity, count));
         }
-        compressor.final_id_limit = match compressor
-            .final_space
-            .get_tail_cluster(&compressor.sessions)
-        {
-            Some(cluster) => cluster.base_final_id + cluster.count,
-            None => final_id_from_id(0),
-        };
-        Ok(compressor)
+        Ok(PersistedState {
+            local_state,
+            cluster_capacity,
+            session_ids,
+            clusters,
+            capacity_history: Vec::new(),
+            max_capacity_hint: DEFAULT_MAX_CAPACITY_HINT,
+            range_bases: Vec::new(),
+        })
     }
 
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::compressor::persistence::DEFAULT_CLUSTER_CAPACITY;
+        use id_types::local_id::local_id_from_id;
 
         #[test]
         fn assert_local_id_alignment() {
@@ -252,7 +443,7 @@ pub mod v1 {
 
             let serialized = compressor.serialize(false);
             let deserialized = IdCompressor::deserialize(&serialized).unwrap();
-            assert_eq!(deserialized.sessions.get_session_count(), 1);
+            assert_eq!(deserialized.finalized.sessions.get_session_count(), 1);
 
             _ = compressor_2.generate_next_id();
             let range = compressor_2.take_next_range();
@@ -260,7 +451,1244 @@ pub mod v1 {
 
             let serialized = compressor.serialize(false);
             let deserialized = IdCompressor::deserialize(&serialized).unwrap();
-            assert_eq!(deserialized.sessions.get_session_count(), 2);
+            assert_eq!(deserialized.finalized.sessions.get_session_count(), 2);
+        }
+
+        #[test]
+        fn test_truncated_input_is_malformed() {
+            let mut compressor = IdCompressor::new();
+            let mut compressor_2 = IdCompressor::new();
+            for _ in 0..3 {
+                _ = compressor.generate_next_id();
+                _ = compressor_2.generate_next_id();
+            }
+            let range = compressor_2.take_next_range();
+            _ = compressor.finalize_range(&range);
+            let range = compressor.take_next_range();
+            _ = compressor.finalize_range(&range);
+            _ = compressor.generate_next_id();
+
+            for serialized in [serialize(&compressor), serialize_with_local(&compressor)] {
+                for len in 0..serialized.len() {
+                    assert!(IdCompressor::deserialize(&serialized[..len]).is_err());
+                }
+                let mut extended = serialized;
+                extended.push(0);
+                assert_eq!(
+                    IdCompressor::deserialize(&extended).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+        }
+
+        #[test]
+        fn test_invalid_cluster_table_is_malformed() {
+            let mut bytes = Vec::new();
+            write_u32_to_vec(&mut bytes, 1);
+            write_u32_to_vec(&mut bytes, false as u32);
+            write_u32_to_vec(&mut bytes, false as u32);
+            write_u64_to_vec(&mut bytes, DEFAULT_CLUSTER_CAPACITY);
+            write_u64_to_vec(&mut bytes, 1);
+            write_u128_to_vec(&mut bytes, StableId::from(SessionId::new()).into());
+            write_u64_to_vec(&mut bytes, 1);
+            let header_len = bytes.len();
+
+            // Out-of-range session index
+            for value in [1, DEFAULT_CLUSTER_CAPACITY, 0] {
+                write_u64_to_vec(&mut bytes, value);
+            }
+            assert_eq!(
+                IdCompressor::deserialize(&bytes).err(),
+                Some(DeserializationError::MalformedInput)
+            );
+
+            // Capacity overflowing the final ID space
+            bytes.truncate(header_len);
+            for value in [0, u64::MAX, 0] {
+                write_u64_to_vec(&mut bytes, value);
+            }
+            assert_eq!(
+                IdCompressor::deserialize(&bytes).err(),
+                Some(DeserializationError::MalformedInput)
+            );
+
+            // Empty and overfilled clusters
+            for (capacity, count) in [
+                (0, 0),
+                (DEFAULT_CLUSTER_CAPACITY, DEFAULT_CLUSTER_CAPACITY + 1),
+            ] {
+                bytes.truncate(header_len);
+                for value in [0, capacity, count] {
+                    write_u64_to_vec(&mut bytes, value);
+                }
+                assert_eq!(
+                    IdCompressor::deserialize(&bytes).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+        }
+    }
+}
+
+pub mod v2 {
+    use super::{
+        check_session_index, DeserializationError, PersistedLocalState, PersistedState,
+        DEFAULT_MAX_CAPACITY_HINT,
+    };
+    #[cfg(test)]
+    use crate::compressor::persistence_utils::write_u32_to_vec;
+    use crate::compressor::{
+        finalized_state::FinalizedState,
+        persistence_utils::{
+            write_u128_to_vec, write_varint_to_vec, zigzag_decode, zigzag_encode, Deserializer,
+        },
+        tables::session_space_normalizer::persistence::v2::{
+            deserialize_normalizer, serialize_normalizer,
+        },
+        IdCompressor,
+    };
+    use id_types::{
+        session_id::{session_id_from_id_u128, session_id_from_uuid_u128},
+        StableId,
+    };
+    use std::mem::size_of;
+
+    // Layout
+    // version: u32
+    // has_local_state: bool as varint
+    // if has_local_state
+    //      session_uuid_u128: u128,
+    //      generated_id_count: varint,
+    //      next_range_base_generation_count: varint,
+    //      persistent_normalizer: PersistenceNormalizer (v2),
+    // cluster_capacity: varint,
+    // session_count: varint,
+    // session_uuid_u128s: u128[],
+    // cluster_count: varint,
+    // cluster_data: (session_index_delta: zigzag varint, capacity: varint, count: varint)[],
+    //
+    // Each session index delta is relative to the session index of the preceding cluster (or zero for the first).
+    // Superseded by v3, which frames this layout (following the version) with integrity metadata.
+
+    #[cfg(test)]
+    use super::get_session_count_delta;
+
+    #[cfg(test)]
+    pub fn serialize(compressor: &IdCompressor) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        write_u32_to_vec(&mut bytes, 2);
+        serialize_body(
+            None,
+            &compressor.finalized,
+            get_session_count_delta(compressor),
+            &mut bytes,
+        );
+        bytes
+    }
+
+    #[cfg(test)]
+    pub fn serialize_with_local(compressor: &IdCompressor) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        write_u32_to_vec(&mut bytes, 2);
+        serialize_body(
+            Some(compressor),
+            &compressor.finalized,
+            get_session_count_delta(compressor),
+            &mut bytes,
+        );
+        bytes
+    }
+
+    // Writes everything following the version, including the local state of the supplied compressor (if any).
+    pub(super) fn serialize_body(
+        local_state: Option<&IdCompressor>,
+        finalized: &FinalizedState,
+        session_count_delta: usize,
+        bytes: &mut Vec<u8>,
+    ) {
+        write_varint_to_vec(bytes, local_state.is_some() as u64);
+        if let Some(compressor) = local_state {
+            write_u128_to_vec(bytes, StableId::from(compressor.session_id).into());
+            write_varint_to_vec(bytes, compressor.generated_id_count);
+            write_varint_to_vec(bytes, compressor.next_range_base_generation_count);
+            serialize_normalizer(&compressor.session_space_normalizer, bytes);
+        }
+        serialize_finalized(finalized, session_count_delta, bytes);
+    }
+
+    fn serialize_finalized(
+        finalized: &FinalizedState,
+        session_count_delta: usize,
+        bytes: &mut Vec<u8>,
+    ) {
+        write_varint_to_vec(bytes, finalized.cluster_capacity);
+        let session_count = finalized.sessions.get_session_count();
+        write_varint_to_vec(bytes, (session_count - session_count_delta) as u64);
+
+        let session_slice =
+            &finalized.sessions.get_session_id_slice()[session_count_delta * size_of::<u128>()..];
+        bytes.extend_from_slice(session_slice);
+
+        write_varint_to_vec(bytes, finalized.final_space.get_cluster_count() as u64);
+        let mut previous_session_index: i64 = 0;
+        finalized
+            .final_space
+            .get_clusters(&finalized.sessions)
+            .for_each(|(id_cluster, cluster_ref)| {
+                let session_index =
+                    (cluster_ref.get_session_space_ref().get_index() - session_count_delta) as i64;
+                write_varint_to_vec(bytes, zigzag_encode(session_index - previous_session_index));
+                write_varint_to_vec(bytes, id_cluster.capacity);
+                write_varint_to_vec(bytes, id_cluster.count);
+                previous_session_index = session_index;
+            });
+    }
+
+    pub(super) fn deserialize(
+        deserializer: &mut Deserializer,
+    ) -> Result<PersistedState, DeserializationError> {
+        let local_state = match deserializer.take_varint()? {
+            0 => None,
+            1 => Some(PersistedLocalState {
+                session_id: session_id_from_uuid_u128(deserializer.take_u128()?),
+                generated_id_count: deserializer.take_varint()?,
+                next_range_base_generation_count: deserializer.take_varint()?,
+                session_space_normalizer: deserialize_normalizer(deserializer)?,
+                pending_ranges: None,
+            }),
+            _ => return Err(DeserializationError::MalformedInput),
+        };
+
+        let cluster_capacity = deserializer.take_varint()?;
+        let session_count = deserializer.take_varint()?;
+        let mut session_ids = Vec::new();
+        for _ in 0..session_count {
+            session_ids.push(session_id_from_id_u128(deserializer.take_u128()?));
+        }
+
+        let cluster_count = deserializer.take_varint()?;
+        let mut clusters = Vec::new();
+        let mut session_index: i64 = 0;
+        for _ in 0..cluster_count {
+            let session_index_delta = zigzag_decode(deserializer.take_varint()?);
+            session_index = session_index
+                .checked_add(session_index_delta)
+                .ok_or(DeserializationError::MalformedInput)?;
+            let checked_session_index = match u64::try_from(session_index) {
+                Ok(session_index) => check_session_index(session_index, &session_ids)?,
+                Err(_) => return Err(DeserializationError::MalformedInput),
+            };
+            let capacity = deserializer.take_varint()?;
+            let count = deserializer.take_varint()?;
+            clusters.push((checked_session_index, capacity, count));
+        }
+        Ok(PersistedState {
+            local_state,
+            cluster_capacity,
+            session_ids,
+            clusters,
+            capacity_history: Vec::new(),
+            max_capacity_hint: DEFAULT_MAX_CAPACITY_HINT,
+            range_bases: Vec::new(),
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::compressor::persistence::v1;
+        use id_types::SessionId;
+
+        fn make_multi_session_compressor() -> IdCompressor {
+            let mut compressor = IdCompressor::new();
+            let mut others: Vec<IdCompressor> = (0..5).map(|_| IdCompressor::new()).collect();
+            for round in 0..20 {
+                for other in others.iter_mut() {
+                    for _ in 0..(round % 7 + 1) {
+                        _ = other.generate_next_id();
+                    }
+                    let range = other.take_next_range();
+                    _ = compressor.finalize_range(&range);
+                }
+                _ = compressor.generate_next_id();
+                let range = compressor.take_next_range();
+                _ = compressor.finalize_range(&range);
+            }
+            _ = compressor.generate_next_id();
+            // Range bases are not persisted by this format
+            compressor.finalized.sessions.clear_range_bases();
+            compressor
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            let compressor = make_multi_session_compressor();
+            for with_local in [false, true] {
+                let serialized = match with_local {
+                    false => serialize(&compressor),
+                    true => serialize_with_local(&compressor),
+                };
+                let deserialized = IdCompressor::deserialize(&serialized).unwrap();
+                assert!(compressor.equals_test_only(&deserialized, with_local));
+            }
+        }
+
+        #[test]
+        fn test_smaller_than_v1() {
+            let compressor = make_multi_session_compressor();
+            assert!(serialize(&compressor).len() < v1::serialize(&compressor).len());
+            assert!(
+                serialize_with_local(&compressor).len()
+                    < v1::serialize_with_local(&compressor).len()
+            );
+        }
+
+        #[test]
+        fn test_reads_v1() {
+            let compressor = make_multi_session_compressor();
+            let deserialized = IdCompressor::deserialize(&v1::serialize(&compressor)).unwrap();
+            assert!(compressor.equals_test_only(&deserialized, false));
+            let deserialized =
+                IdCompressor::deserialize(&v1::serialize_with_local(&compressor)).unwrap();
+            assert!(compressor.equals_test_only(&deserialized, true));
+        }
+
+        #[test]
+        fn test_truncated_input_is_malformed() {
+            let compressor = make_multi_session_compressor();
+            for serialized in [serialize(&compressor), serialize_with_local(&compressor)] {
+                for len in 0..serialized.len() {
+                    assert!(IdCompressor::deserialize(&serialized[..len]).is_err());
+                }
+                let mut extended = serialized;
+                extended.push(0);
+                assert_eq!(
+                    IdCompressor::deserialize(&extended).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+        }
+
+        #[test]
+        fn test_invalid_cluster_is_malformed() {
+            // Empty and overfilled clusters
+            for (capacity, count) in [(0, 0), (1, 2)] {
+                let mut compressor = make_multi_session_compressor();
+                let cluster_ref = compressor
+                    .finalized
+                    .final_space
+                    .get_clusters(&compressor.finalized.sessions)
+                    .map(|(_, cluster_ref)| cluster_ref)
+                    .next()
+                    .unwrap();
+                let cluster = compressor.finalized.sessions.deref_cluster_mut(cluster_ref);
+                cluster.capacity = capacity;
+                cluster.count = count;
+                assert_eq!(
+                    IdCompressor::deserialize(&serialize(&compressor)).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+        }
+
+        #[test]
+        fn test_invalid_session_index_delta_is_malformed() {
+            let mut bytes = Vec::new();
+            write_u32_to_vec(&mut bytes, 2);
+            write_varint_to_vec(&mut bytes, false as u64);
+            write_varint_to_vec(&mut bytes, 512);
+            write_varint_to_vec(&mut bytes, 1);
+            write_u128_to_vec(&mut bytes, StableId::from(SessionId::new()).into());
+            write_varint_to_vec(&mut bytes, 1);
+            write_varint_to_vec(&mut bytes, zigzag_encode(-1));
+            write_varint_to_vec(&mut bytes, 512);
+            write_varint_to_vec(&mut bytes, 0);
+            assert_eq!(
+                IdCompressor::deserialize(&bytes).err(),
+                Some(DeserializationError::MalformedInput)
+            );
+        }
+    }
+}
+
+pub mod v3 {
+    #[cfg(test)]
+    use super::get_session_count_delta;
+    use super::{v2, DeserializationError, PersistedState};
+    use crate::compressor::persistence_utils::{
+        crc32, write_u32_to_vec, write_u64_to_vec, Deserializer,
+    };
+    #[cfg(test)]
+    use crate::compressor::IdCompressor;
+
+    // "IDCP"
+    const MAGIC: u32 = 0x5043_4449;
+
+    // Layout
+    // version: u32
+    // magic: u32
+    // payload_length: u64
+    // payload: v2 layout following its version
+    // checksum: u32 (CRC32 of all preceding bytes)
+    //
+    // Superseded by v4, which uses the same frame around a different payload.
+
+    #[cfg(test)]
+    pub fn serialize(compressor: &IdCompressor, include_local_state: bool) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        v2::serialize_body(
+            include_local_state.then_some(compressor),
+            &compressor.finalized,
+            get_session_count_delta(compressor),
+            &mut payload,
+        );
+        frame(3, &payload)
+    }
+
+    // Wraps the payload in a frame with the supplied version.
+    pub(super) fn frame(version: u32, payload: &[u8]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(payload.len() + 20);
+        write_u32_to_vec(&mut bytes, version);
+        write_u32_to_vec(&mut bytes, MAGIC);
+        write_u64_to_vec(&mut bytes, payload.len() as u64);
+        bytes.extend_from_slice(payload);
+        let checksum = crc32(&bytes);
+        write_u32_to_vec(&mut bytes, checksum);
+        bytes
+    }
+
+    pub(super) fn deserialize<'a>(
+        bytes: &'a [u8],
+        deserializer: &mut Deserializer<'a>,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut payload_deserializer = Deserializer::new(unframe(bytes, deserializer)?);
+        let persisted = v2::deserialize(&mut payload_deserializer)?;
+        if !payload_deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(persisted)
+    }
+
+    // Reads the frame following the version, verifying the checksum, and returns the payload.
+    pub(super) fn unframe<'a>(
+        bytes: &'a [u8],
+        deserializer: &mut Deserializer<'a>,
+    ) -> Result<&'a [u8], DeserializationError> {
+        if deserializer.take_u32()? != MAGIC {
+            return Err(DeserializationError::MalformedInput);
+        }
+        let payload_length = deserializer.take_u64()?;
+        let payload = deserializer.take_slice(payload_length)?;
+        let checksum = deserializer.take_u32()?;
+        if !deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        // The checksum is verified before the payload is parsed so that corruption is reported as such.
+        if crc32(&bytes[..bytes.len() - 4]) != checksum {
+            return Err(DeserializationError::ChecksumMismatch);
+        }
+        Ok(payload)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn make_compressor() -> IdCompressor {
+            let mut compressor = IdCompressor::new();
+            let mut compressor_2 = IdCompressor::new();
+            for _ in 0..3 {
+                _ = compressor.generate_next_id();
+                _ = compressor_2.generate_next_id();
+            }
+            let range = compressor_2.take_next_range();
+            _ = compressor.finalize_range(&range);
+            let range = compressor.take_next_range();
+            _ = compressor.finalize_range(&range);
+            _ = compressor.generate_next_id();
+            // Range bases are not persisted by this format
+            compressor.finalized.sessions.clear_range_bases();
+            compressor
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            let compressor = make_compressor();
+            for with_local in [false, true] {
+                let serialized = serialize(&compressor, with_local);
+                let deserialized = IdCompressor::deserialize(&serialized).unwrap();
+                assert!(compressor.equals_test_only(&deserialized, with_local));
+            }
+        }
+
+        #[test]
+        fn test_reads_v2() {
+            let compressor = make_compressor();
+            let deserialized = IdCompressor::deserialize(&v2::serialize(&compressor)).unwrap();
+            assert!(compressor.equals_test_only(&deserialized, false));
+        }
+
+        #[test]
+        fn test_bit_flips_are_detected() {
+            let compressor = make_compressor();
+            let serialized = serialize(&compressor, true);
+            // Skip the version, which routes to other readers when corrupted.
+            for index in 4..serialized.len() {
+                for bit in 0..8 {
+                    let mut corrupted = serialized.clone();
+                    corrupted[index] ^= 1 << bit;
+                    assert!(IdCompressor::deserialize(&corrupted).is_err());
+                }
+            }
+            let mut corrupted = serialized;
+            let payload_index = 16;
+            corrupted[payload_index] ^= 1;
+            assert_eq!(
+                IdCompressor::deserialize(&corrupted).err(),
+                Some(DeserializationError::ChecksumMismatch)
+            );
+        }
+
+        #[test]
+        fn test_framing_is_malformed() {
+            let compressor = make_compressor();
+            let serialized = serialize(&compressor, false);
+            for len in 0..serialized.len() {
+                assert_eq!(
+                    IdCompressor::deserialize(&serialized[..len]).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+            let mut extended = serialized;
+            extended.push(0);
+            assert_eq!(
+                IdCompressor::deserialize(&extended).err(),
+                Some(DeserializationError::MalformedInput)
+            );
+        }
+    }
+}
+
+pub mod v4 {
+    use super::{v2, v3, DeserializationError, PersistedState};
+    use crate::compressor::{
+        finalized_state::FinalizedState,
+        persistence_utils::{write_varint_to_vec, Deserializer},
+        IdCompressor,
+    };
+
+    // Layout
+    // version: u32
+    // frame: v3 frame around the following payload
+    // payload:
+    //      v2 layout following its version
+    //      if has_local_state
+    //          pending_range_count: varint,
+    //          pending_ranges: (first generation count: varint, count: varint)[],
+
+    #[cfg(test)]
+    pub fn serialize(compressor: &IdCompressor, include_local_state: bool) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        serialize_payload(
+            include_local_state.then_some(compressor),
+            &compressor.finalized,
+            super::get_session_count_delta(compressor),
+            &mut payload,
+        );
+        v3::frame(4, &payload)
+    }
+
+    // Writes the payload, including the local state of the supplied compressor (if any).
+    pub(super) fn serialize_payload(
+        local_state: Option<&IdCompressor>,
+        finalized: &FinalizedState,
+        session_count_delta: usize,
+        payload: &mut Vec<u8>,
+    ) {
+        v2::serialize_body(local_state, finalized, session_count_delta, payload);
+        if let Some(compressor) = local_state {
+            write_varint_to_vec(payload, compressor.pending_ranges.len() as u64);
+            for &(base, count, _) in &compressor.pending_ranges {
+                write_varint_to_vec(payload, base);
+                write_varint_to_vec(payload, count);
+            }
+        }
+    }
+
+    pub(super) fn deserialize<'a>(
+        bytes: &'a [u8],
+        deserializer: &mut Deserializer<'a>,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut payload_deserializer = Deserializer::new(v3::unframe(bytes, deserializer)?);
+        let persisted = deserialize_payload(&mut payload_deserializer)?;
+        if !payload_deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(persisted)
+    }
+
+    pub(super) fn deserialize_payload(
+        payload_deserializer: &mut Deserializer,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut persisted = v2::deserialize(payload_deserializer)?;
+        if let Some(local_state) = persisted.local_state.as_mut() {
+            let pending_range_count = payload_deserializer.take_varint()?;
+            let mut pending_ranges = Vec::new();
+            // Pending ranges must be ascending, disjoint and taken before the next range base.
+            let mut next_base = 1;
+            for _ in 0..pending_range_count {
+                let base = payload_deserializer.take_varint()?;
+                let count = payload_deserializer.take_varint()?;
+                match base.checked_add(count) {
+                    Some(end) if base >= next_base && count > 0 => {
+                        if end > local_state.next_range_base_generation_count {
+                            return Err(DeserializationError::MalformedInput);
+                        }
+                        next_base = end;
+                    }
+                    _ => return Err(DeserializationError::MalformedInput),
+                }
+                pending_ranges.push((base, count, None));
+            }
+            local_state.pending_ranges = Some(pending_ranges);
+        }
+        Ok(persisted)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn make_compressor() -> IdCompressor {
+            let mut compressor = IdCompressor::new();
+            let mut compressor_2 = IdCompressor::new();
+            for _ in 0..3 {
+                _ = compressor.generate_next_id();
+                _ = compressor_2.generate_next_id();
+            }
+            let range = compressor_2.take_next_range();
+            _ = compressor.finalize_range(&range);
+            let range = compressor.take_next_range();
+            _ = compressor.finalize_range(&range);
+            for _ in 0..2 {
+                _ = compressor.generate_next_id();
+                _ = compressor.take_next_range();
+            }
+            _ = compressor.generate_next_id();
+            // Range bases are not persisted by this format
+            compressor.finalized.sessions.clear_range_bases();
+            compressor
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            let compressor = make_compressor();
+            assert_eq!(compressor.get_pending_ranges().len(), 2);
+            for with_local in [false, true] {
+                let serialized = serialize(&compressor, with_local);
+                let deserialized = IdCompressor::deserialize(&serialized).unwrap();
+                assert!(compressor.equals_test_only(&deserialized, with_local));
+            }
+        }
+
+        #[test]
+        fn test_reads_v3() {
+            let compressor = make_compressor();
+            let deserialized =
+                IdCompressor::deserialize(&v3::serialize(&compressor, true)).unwrap();
+            assert!(compressor.equals_test_only(&deserialized, false));
+            let pending_ranges = deserialized.get_pending_ranges();
+            assert_eq!(pending_ranges.len(), 1);
+            assert_eq!(pending_ranges[0].range, Some((4, 2)));
+        }
+
+        #[test]
+        fn test_invalid_pending_ranges_are_malformed() {
+            let mut compressor = make_compressor();
+            for pending_ranges in [
+                vec![(4, 0)],
+                vec![(4, 1), (4, 1)],
+                vec![(0, 1)],
+                vec![(5, 2)],
+                vec![(4, u64::MAX)],
+            ] {
+                compressor.pending_ranges = pending_ranges
+                    .into_iter()
+                    .map(|(base, count)| (base, count, None))
+                    .collect();
+                assert_eq!(
+                    IdCompressor::deserialize(&serialize(&compressor, true)).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+        }
+    }
+}
+
+pub mod v5 {
+    use super::{v3, v4, DeserializationError, PersistedState};
+    use crate::compressor::{
+        finalized_state::FinalizedState,
+        persistence_utils::{write_varint_to_vec, Deserializer},
+        IdCompressor,
+    };
+    use id_types::final_id::get_id_from_final_id;
+
+    // Layout
+    // version: u32
+    // frame: v3 frame around the following payload
+    // payload:
+    //      v4 payload
+    //      capacity_change_count: varint,
+    //      capacity_changes: (final_id_limit: varint, capacity: varint)[],
+
+    #[cfg(test)]
+    pub fn serialize(compressor: &IdCompressor, include_local_state: bool) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        serialize_payload(
+            include_local_state.then_some(compressor),
+            &compressor.finalized,
+            super::get_session_count_delta(compressor),
+            &mut payload,
+        );
+        v3::frame(5, &payload)
+    }
+
+    // Writes the payload, including the local state of the supplied compressor (if any).
+    pub(super) fn serialize_payload(
+        local_state: Option<&IdCompressor>,
+        finalized: &FinalizedState,
+        session_count_delta: usize,
+        payload: &mut Vec<u8>,
+    ) {
+        v4::serialize_payload(local_state, finalized, session_count_delta, payload);
+        write_varint_to_vec(payload, finalized.capacity_history.len() as u64);
+        for &(final_id_limit, capacity) in &finalized.capacity_history {
+            write_varint_to_vec(payload, get_id_from_final_id(final_id_limit));
+            write_varint_to_vec(payload, capacity);
+        }
+    }
+
+    pub(super) fn deserialize<'a>(
+        bytes: &'a [u8],
+        deserializer: &mut Deserializer<'a>,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut payload_deserializer = Deserializer::new(v3::unframe(bytes, deserializer)?);
+        let persisted = deserialize_payload(&mut payload_deserializer)?;
+        if !payload_deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(persisted)
+    }
+
+    pub(super) fn deserialize_payload(
+        payload_deserializer: &mut Deserializer,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut persisted = v4::deserialize_payload(payload_deserializer)?;
+        let capacity_change_count = payload_deserializer.take_varint()?;
+        // Changes must be ordered by the final ID limit at which they were applied, and set valid capacities.
+        let mut previous_final_id_limit = 0;
+        for _ in 0..capacity_change_count {
+            let final_id_limit = payload_deserializer.take_varint()?;
+            let capacity = payload_deserializer.take_varint()?;
+            if final_id_limit < previous_final_id_limit || capacity == 0 {
+                return Err(DeserializationError::MalformedInput);
+            }
+            previous_final_id_limit = final_id_limit;
+            persisted.capacity_history.push((final_id_limit, capacity));
+        }
+        Ok(persisted)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use id_types::final_id::final_id_from_id;
+
+        fn make_compressor() -> IdCompressor {
+            let mut compressor = IdCompressor::new();
+            for capacity in [5, 10] {
+                let change = compressor.propose_capacity_change(capacity).unwrap();
+                compressor.apply_capacity_change(&change).unwrap();
+                _ = compressor.generate_next_id();
+                let range = compressor.take_next_range();
+                _ = compressor.finalize_range(&range);
+            }
+            _ = compressor.generate_next_id();
+            _ = compressor.take_next_range();
+            // Range bases are not persisted by this format
+            compressor.finalized.sessions.clear_range_bases();
+            compressor
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            let compressor = make_compressor();
+            assert_eq!(compressor.finalized.capacity_history.len(), 2);
+            for with_local in [false, true] {
+                let serialized = serialize(&compressor, with_local);
+                let deserialized = IdCompressor::deserialize(&serialized).unwrap();
+                assert!(compressor.equals_test_only(&deserialized, with_local));
+            }
+        }
+
+        #[test]
+        fn test_reads_v4() {
+            let mut compressor = make_compressor();
+            compressor.finalized.capacity_history.clear();
+            let deserialized =
+                IdCompressor::deserialize(&v4::serialize(&compressor, true)).unwrap();
+            assert!(compressor.equals_test_only(&deserialized, true));
+        }
+
+        #[test]
+        fn test_invalid_capacity_history_is_malformed() {
+            let mut compressor = make_compressor();
+            for capacity_history in [
+                vec![(final_id_from_id(0), 0)],
+                vec![(final_id_from_id(2), 5), (final_id_from_id(1), 5)],
+            ] {
+                compressor.finalized.capacity_history = capacity_history;
+                assert_eq!(
+                    IdCompressor::deserialize(&serialize(&compressor, true)).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+        }
+    }
+}
+
+pub mod v6 {
+    use super::{v3, v5, DeserializationError, PersistedState};
+    use crate::compressor::{
+        finalized_state::FinalizedState,
+        persistence_utils::{write_varint_to_vec, Deserializer},
+        IdCompressor,
+    };
+
+    // Layout
+    // version: u32
+    // frame: v3 frame around the following payload
+    // payload:
+    //      v5 payload
+    //      max_capacity_hint: varint,
+
+    #[cfg(test)]
+    pub fn serialize(compressor: &IdCompressor, include_local_state: bool) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        serialize_payload(
+            include_local_state.then_some(compressor),
+            &compressor.finalized,
+            super::get_session_count_delta(compressor),
+            &mut payload,
+        );
+        v3::frame(6, &payload)
+    }
+
+    // Writes the payload, including the local state of the supplied compressor (if any).
+    pub(super) fn serialize_payload(
+        local_state: Option<&IdCompressor>,
+        finalized: &FinalizedState,
+        session_count_delta: usize,
+        payload: &mut Vec<u8>,
+    ) {
+        v5::serialize_payload(local_state, finalized, session_count_delta, payload);
+        write_varint_to_vec(payload, finalized.max_capacity_hint);
+    }
+
+    pub(super) fn deserialize<'a>(
+        bytes: &'a [u8],
+        deserializer: &mut Deserializer<'a>,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut payload_deserializer = Deserializer::new(v3::unframe(bytes, deserializer)?);
+        let persisted = deserialize_payload(&mut payload_deserializer)?;
+        if !payload_deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(persisted)
+    }
+
+    pub(super) fn deserialize_payload(
+        payload_deserializer: &mut Deserializer,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut persisted = v5::deserialize_payload(payload_deserializer)?;
+        persisted.max_capacity_hint = payload_deserializer.take_varint()?;
+        if persisted.max_capacity_hint == 0 {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(persisted)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn make_compressor() -> IdCompressor {
+            let mut compressor = IdCompressor::new();
+            compressor.set_max_capacity_hint(100).unwrap();
+            compressor.set_capacity_hint(Some(1000)).unwrap();
+            _ = compressor.generate_next_id();
+            let range = compressor.take_next_range();
+            _ = compressor.finalize_range(&range);
+            // Range bases are not persisted by this format
+            compressor.finalized.sessions.clear_range_bases();
+            compressor
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            let compressor = make_compressor();
+            for with_local in [false, true] {
+                let serialized = serialize(&compressor, with_local);
+                let deserialized = IdCompressor::deserialize(&serialized).unwrap();
+                assert!(compressor.equals_test_only(&deserialized, with_local));
+            }
+        }
+
+        #[test]
+        fn test_reads_v5() {
+            let mut compressor = make_compressor();
+            compressor.finalized.max_capacity_hint = IdCompressor::get_default_max_capacity_hint();
+            let deserialized =
+                IdCompressor::deserialize(&v5::serialize(&compressor, true)).unwrap();
+            assert!(compressor.equals_test_only(&deserialized, true));
+        }
+
+        #[test]
+        fn test_invalid_max_capacity_hint_is_malformed() {
+            let mut compressor = make_compressor();
+            compressor.finalized.max_capacity_hint = 0;
+            assert_eq!(
+                IdCompressor::deserialize(&serialize(&compressor, true)).err(),
+                Some(DeserializationError::MalformedInput)
+            );
+        }
+    }
+}
+
+pub mod v7 {
+    use super::{v3, v6, DeserializationError, PersistedState};
+    use crate::compressor::{
+        finalized_state::FinalizedState,
+        persistence_utils::{write_varint_to_vec, Deserializer},
+        IdCompressor,
+    };
+
+    // Layout
+    // version: u32
+    // frame: v3 frame around the following payload
+    // payload:
+    //      v6 payload
+    //      for each serialized session, in session table order
+    //          range_base_count: varint,
+    //          range_base_deltas: varint[],
+    //
+    // Each range base delta is relative to the preceding range base of the session (or zero for the first).
+
+    #[cfg(test)]
+    pub fn serialize(compressor: &IdCompressor, include_local_state: bool) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        serialize_payload(
+            include_local_state.then_some(compressor),
+            &compressor.finalized,
+            super::get_session_count_delta(compressor),
+            &mut payload,
+        );
+        v3::frame(7, &payload)
+    }
+
+    // Writes the payload, including the local state of the supplied compressor (if any).
+    pub(super) fn serialize_payload(
+        local_state: Option<&IdCompressor>,
+        finalized: &FinalizedState,
+        session_count_delta: usize,
+        payload: &mut Vec<u8>,
+    ) {
+        v6::serialize_payload(local_state, finalized, session_count_delta, payload);
+        for session_space in finalized
+            .sessions
+            .get_session_spaces()
+            .skip(session_count_delta)
+        {
+            let range_bases = session_space.get_range_bases();
+            write_varint_to_vec(payload, range_bases.len() as u64);
+            let mut previous_range_base = 0;
+            for &range_base in range_bases {
+                write_varint_to_vec(payload, range_base - previous_range_base);
+                previous_range_base = range_base;
+            }
+        }
+    }
+
+    pub(super) fn deserialize<'a>(
+        bytes: &'a [u8],
+        deserializer: &mut Deserializer<'a>,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut payload_deserializer = Deserializer::new(v3::unframe(bytes, deserializer)?);
+        let persisted = deserialize_payload(&mut payload_deserializer)?;
+        if !payload_deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(persisted)
+    }
+
+    pub(super) fn deserialize_payload(
+        payload_deserializer: &mut Deserializer,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut persisted = v6::deserialize_payload(payload_deserializer)?;
+        for _ in 0..persisted.session_ids.len() {
+            let range_base_count = payload_deserializer.take_varint()?;
+            let mut range_bases = Vec::new();
+            // Range bases must be strictly ascending, and so every delta (including the first) must be non-zero.
+            let mut range_base: u64 = 0;
+            for _ in 0..range_base_count {
+                range_base = match payload_deserializer.take_varint()? {
+                    0 => return Err(DeserializationError::MalformedInput),
+                    delta => range_base
+                        .checked_add(delta)
+                        .ok_or(DeserializationError::MalformedInput)?,
+                };
+                range_bases.push(range_base);
+            }
+            persisted.range_bases.push(range_bases);
+        }
+        Ok(persisted)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::compressor::persistence::v6;
+
+        fn make_compressor() -> IdCompressor {
+            let mut compressor = IdCompressor::new();
+            let mut compressor_2 = IdCompressor::new();
+            for count in [2, 3, 1] {
+                for _ in 0..count {
+                    _ = compressor.generate_next_id();
+                    _ = compressor_2.generate_next_id();
+                }
+                let range = compressor_2.take_next_range();
+                _ = compressor.finalize_range(&range);
+                let range = compressor.take_next_range();
+                _ = compressor.finalize_range(&range);
+            }
+            _ = compressor.generate_next_id();
+            compressor
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            let compressor = make_compressor();
+            for with_local in [false, true] {
+                let serialized = serialize(&compressor, with_local);
+                let deserialized = IdCompressor::deserialize(&serialized).unwrap();
+                assert!(compressor.equals_test_only(&deserialized, with_local));
+                let session_space_ref = *deserialized
+                    .finalized
+                    .sessions
+                    .get(compressor.get_local_session_id())
+                    .unwrap();
+                assert_eq!(
+                    deserialized
+                        .finalized
+                        .sessions
+                        .deref_session_space(session_space_ref)
+                        .get_range_bases(),
+                    [1, 3, 6]
+                );
+            }
+        }
+
+        #[test]
+        fn test_reads_v6() {
+            let mut compressor = make_compressor();
+            compressor.finalized.sessions.clear_range_bases();
+            let deserialized =
+                IdCompressor::deserialize(&v6::serialize(&compressor, true)).unwrap();
+            assert!(compressor.equals_test_only(&deserialized, true));
+        }
+
+        #[test]
+        fn test_invalid_range_bases_are_malformed() {
+            let compressor = make_compressor();
+            let session_count = compressor.finalized.sessions.get_session_count();
+            // Replaces the range base deltas of the final session: a repeated base, a zero base, and a base beyond the
+            // session's finalized IDs
+            for range_base_deltas in [vec![1, 0], vec![0], vec![7]] {
+                let mut payload = Vec::new();
+                v6::serialize_payload(None, &compressor.finalized, 0, &mut payload);
+                for session_space in compressor
+                    .finalized
+                    .sessions
+                    .get_session_spaces()
+                    .take(session_count - 1)
+                {
+                    let range_bases = session_space.get_range_bases();
+                    write_varint_to_vec(&mut payload, range_bases.len() as u64);
+                    let mut previous_range_base = 0;
+                    for &range_base in range_bases {
+                        write_varint_to_vec(&mut payload, range_base - previous_range_base);
+                        previous_range_base = range_base;
+                    }
+                }
+                write_varint_to_vec(&mut payload, range_base_deltas.len() as u64);
+                for delta in range_base_deltas {
+                    write_varint_to_vec(&mut payload, delta);
+                }
+                assert_eq!(
+                    IdCompressor::deserialize(&v3::frame(7, &payload)).err(),
+                    Some(DeserializationError::MalformedInput)
+                );
+            }
+        }
+    }
+}
+
+pub mod v8 {
+    use super::{get_session_count_delta, v3, v7, DeserializationError, PersistedState};
+    use crate::compressor::{
+        finalized_state::FinalizedState,
+        persistence_utils::{write_varint_to_vec, Deserializer},
+        IdCompressor,
+    };
+
+    // Layout
+    // version: u32
+    // frame: v3 frame around the following payload
+    // payload:
+    //      v7 payload
+    //      if has_local_state
+    //          pending_range_capacity_hints: varint[], one per pending range in the v4 pending range table
+    //
+    // A capacity hint of zero denotes a range taken without a hint.
+
+    pub fn serialize(compressor: &IdCompressor, include_local_state: bool) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        serialize_payload(
+            include_local_state.then_some(compressor),
+            &compressor.finalized,
+            get_session_count_delta(compressor),
+            &mut payload,
+        );
+        v3::frame(8, &payload)
+    }
+
+    // Serializes finalized state alone, in the same form as a compressor serialized without local state.
+    pub fn serialize_finalized(finalized: &FinalizedState) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        serialize_payload(None, finalized, 0, &mut payload);
+        v3::frame(8, &payload)
+    }
+
+    // Writes the payload, including the local state of the supplied compressor (if any).
+    pub(super) fn serialize_payload(
+        local_state: Option<&IdCompressor>,
+        finalized: &FinalizedState,
+        session_count_delta: usize,
+        payload: &mut Vec<u8>,
+    ) {
+        v7::serialize_payload(local_state, finalized, session_count_delta, payload);
+        if let Some(compressor) = local_state {
+            for &(_, _, capacity_hint) in &compressor.pending_ranges {
+                write_varint_to_vec(payload, capacity_hint.unwrap_or(0));
+            }
+        }
+    }
+
+    pub(super) fn deserialize<'a>(
+        bytes: &'a [u8],
+        deserializer: &mut Deserializer<'a>,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut payload_deserializer = Deserializer::new(v3::unframe(bytes, deserializer)?);
+        let persisted = deserialize_payload(&mut payload_deserializer)?;
+        if !payload_deserializer.is_empty() {
+            return Err(DeserializationError::MalformedInput);
+        }
+        Ok(persisted)
+    }
+
+    pub(super) fn deserialize_payload(
+        payload_deserializer: &mut Deserializer,
+    ) -> Result<PersistedState, DeserializationError> {
+        let mut persisted = v7::deserialize_payload(payload_deserializer)?;
+        if let Some(pending_ranges) = persisted
+            .local_state
+            .as_mut()
+            .and_then(|local_state| local_state.pending_ranges.as_mut())
+        {
+            for (_, _, capacity_hint) in pending_ranges {
+                *capacity_hint = match payload_deserializer.take_varint()? {
+                    0 => None,
+                    hint => Some(hint),
+                };
+            }
+        }
+        Ok(persisted)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn make_compressor() -> IdCompressor {
+            let mut compressor = IdCompressor::new();
+            for capacity_hint in [Some(3), None, Some(5)] {
+                compressor.set_capacity_hint(capacity_hint).unwrap();
+                _ = compressor.generate_next_id();
+                _ = compressor.take_next_range();
+            }
+            compressor.set_capacity_hint(Some(7)).unwrap();
+            compressor
+        }
+
+        #[test]
+        fn test_roundtrip() {
+            let compressor = make_compressor();
+            for with_local in [false, true] {
+                let serialized = serialize(&compressor, with_local);
+                let deserialized = IdCompressor::deserialize(&serialized).unwrap();
+                assert!(compressor.equals_test_only(&deserialized, with_local));
+            }
+            let deserialized = IdCompressor::deserialize(&serialize(&compressor, true)).unwrap();
+            let capacity_hints: Vec<Option<u64>> = deserialized
+                .get_pending_ranges()
+                .iter()
+                .map(|range| range.capacity_hint)
+                .collect();
+            assert_eq!(capacity_hints, [Some(3), None, Some(5)]);
+        }
+
+        #[test]
+        fn test_reads_v7() {
+            let mut compressor = make_compressor();
+            let deserialized =
+                IdCompressor::deserialize(&v7::serialize(&compressor, true)).unwrap();
+            assert!(deserialized
+                .get_pending_ranges()
+                .iter()
+                .all(|range| range.capacity_hint.is_none()));
+            for (_, _, capacity_hint) in &mut compressor.pending_ranges {
+                *capacity_hint = None;
+            }
+            assert!(compressor.equals_test_only(&deserialized, true));
+        }
+
+        #[test]
+        fn test_truncated_capacity_hints_are_malformed() {
+            let compressor = make_compressor();
+            let mut payload = Vec::new();
+            serialize_payload(
+                Some(&compressor),
+                &compressor.finalized,
+                get_session_count_delta(&compressor),
+                &mut payload,
+            );
+            _ = payload.pop();
+            assert_eq!(
+                IdCompressor::deserialize(&v3::frame(8, &payload)).err(),
+                Some(DeserializationError::MalformedInput)
+            );
         }
     }
 }
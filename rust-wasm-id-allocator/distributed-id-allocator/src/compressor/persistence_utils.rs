@@ -1,3 +1,5 @@
+use super::persistence::DeserializationError;
+
 pub struct Deserializer<'a> {
     bytes: &'a [u8],
 }
@@ -7,26 +9,66 @@ impl<'a> Deserializer<'a> {
         Self { bytes }
     }
 
-    pub fn take_u32(&mut self) -> u32 {
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn take_u32(&mut self) -> Result<u32, DeserializationError> {
         self.take_one(u32::from_le_bytes)
     }
 
-    pub fn take_u64(&mut self) -> u64 {
+    pub fn take_u64(&mut self) -> Result<u64, DeserializationError> {
         self.take_one(u64::from_le_bytes)
     }
 
-    pub fn take_u128(&mut self) -> u128 {
+    pub fn take_u128(&mut self) -> Result<u128, DeserializationError> {
         self.take_one(u128::from_le_bytes)
     }
 
+    pub fn take_slice(&mut self, len: u64) -> Result<&'a [u8], DeserializationError> {
+        if (self.bytes.len() as u64) < len {
+            return Err(DeserializationError::MalformedInput);
+        }
+        let (taken, remaining) = self.bytes.split_at(len as usize);
+        self.bytes = remaining;
+        Ok(taken)
+    }
+
+    // Reads an unsigned LEB128 varint, rejecting encodings that overflow a u64.
+    pub fn take_varint(&mut self) -> Result<u64, DeserializationError> {
+        let mut result: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let (&byte, remaining) = self
+                .bytes
+                .split_first()
+                .ok_or(DeserializationError::MalformedInput)?;
+            self.bytes = remaining;
+            if shift == 63 && byte > 1 {
+                return Err(DeserializationError::MalformedInput);
+            }
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
     #[inline]
-    fn take_one<FBuild, T, const SIZE: usize>(&mut self, builder: FBuild) -> T
+    fn take_one<FBuild, T, const SIZE: usize>(
+        &mut self,
+        builder: FBuild,
+    ) -> Result<T, DeserializationError>
     where
         FBuild: Fn([u8; SIZE]) -> T,
     {
-        let val = builder(self.bytes[..SIZE].try_into().unwrap());
-        self.bytes = &self.bytes[SIZE..];
-        val
+        if self.bytes.len() < SIZE {
+            return Err(DeserializationError::MalformedInput);
+        }
+        let (taken, remaining) = self.bytes.split_at(SIZE);
+        self.bytes = remaining;
+        Ok(builder(taken.try_into().unwrap()))
     }
 }
 
@@ -53,6 +95,55 @@ pub fn write_u128_to_vec(buffer: &mut Vec<u8>, num: u128) {
     write_to_vec(buffer, num, |val: u128| val.to_le_bytes());
 }
 
+pub fn write_varint_to_vec(buffer: &mut Vec<u8>, mut num: u64) {
+    while num >= 0x80 {
+        buffer.push((num as u8) | 0x80);
+        num >>= 7;
+    }
+    buffer.push(num as u8);
+}
+
+// Maps signed integers to unsigned integers such that values of small magnitude have small encodings.
+#[inline]
+pub fn zigzag_encode(num: i64) -> u64 {
+    ((num << 1) ^ (num >> 63)) as u64
+}
+
+#[inline]
+pub fn zigzag_decode(num: u64) -> i64 {
+    ((num >> 1) as i64) ^ -((num & 1) as i64)
+}
+
+const CRC32_TABLE: [u32; 256] = make_crc32_table();
+
+// Builds the lookup table for the reflected IEEE 802.3 polynomial.
+const fn make_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+// Computes the CRC32 (IEEE) checksum of the supplied bytes.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use id_types::{SessionId, StableId};
@@ -80,12 +171,12 @@ mod tests {
 
         let mut u64s = vec![];
         for _ in 0..3 {
-            u64s.push(deser.take_u64())
+            u64s.push(deser.take_u64().unwrap())
         }
 
         let mut u128s = vec![];
         for _ in 0..3 {
-            u128s.push(deser.take_u128())
+            u128s.push(deser.take_u128().unwrap())
         }
 
         assert_eq!(u64s, vec![1, 2, 3]);
@@ -93,11 +184,80 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
+    fn test_varints() {
+        let values = [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX as u64, u64::MAX];
+        let mut bytes: Vec<u8> = Vec::new();
+        for value in values {
+            write_varint_to_vec(&mut bytes, value);
+        }
+        assert_eq!(bytes[..5], [0, 1, 0x7f, 0x80, 0x01]);
+        let mut deser = Deserializer::new(&bytes);
+        for value in values {
+            assert_eq!(deser.take_varint(), Ok(value));
+        }
+        assert!(deser.is_empty());
+        assert_eq!(
+            deser.take_varint(),
+            Err(DeserializationError::MalformedInput)
+        );
+
+        // Unterminated
+        let mut deser = Deserializer::new(&[0x80, 0x80]);
+        assert_eq!(
+            deser.take_varint(),
+            Err(DeserializationError::MalformedInput)
+        );
+
+        // Overflowing a u64
+        let mut overflowing = [0xff; 10];
+        overflowing[9] = 0x02;
+        let mut deser = Deserializer::new(&overflowing);
+        assert_eq!(
+            deser.take_varint(),
+            Err(DeserializationError::MalformedInput)
+        );
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(&[]), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414f_a339
+        );
+    }
+
+    #[test]
+    fn test_take_slice() {
+        let bytes = [1, 2, 3];
+        let mut deser = Deserializer::new(&bytes);
+        assert_eq!(deser.take_slice(2), Ok(&bytes[..2]));
+        assert_eq!(
+            deser.take_slice(2),
+            Err(DeserializationError::MalformedInput)
+        );
+        assert_eq!(deser.take_slice(1), Ok(&bytes[2..]));
+        assert!(deser.is_empty());
+    }
+
+    #[test]
+    fn test_zigzag() {
+        for (value, encoded) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (i64::MAX, u64::MAX - 1)] {
+            assert_eq!(zigzag_encode(value), encoded);
+            assert_eq!(zigzag_decode(encoded), value);
+        }
+        assert_eq!(zigzag_decode(zigzag_encode(i64::MIN)), i64::MIN);
+    }
+
+    #[test]
     fn test_malformed_input() {
         let mut bytes: Vec<u8> = Vec::new();
         write_u64_to_vec(&mut bytes, 42);
         let mut deser = Deserializer::new(&bytes);
-        _ = deser.take_u128();
+        assert_eq!(deser.take_u128(), Err(DeserializationError::MalformedInput));
+        assert_eq!(deser.take_u64(), Ok(42));
+        assert!(deser.is_empty());
+        assert_eq!(deser.take_u32(), Err(DeserializationError::MalformedInput));
     }
 }
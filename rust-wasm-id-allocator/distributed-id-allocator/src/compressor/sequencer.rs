@@ -0,0 +1,330 @@
+use super::finalized_state::FinalizedState;
+use super::persistence::{self, DeserializationError};
+use super::{
+    BatchFinalizationError, CapacityChange, ClusterSnapshot, FinalizationOutcome, IdRange,
+    IdStatus, PlannedFinalization, SpaceReport, TelemetryStats,
+};
+use id_types::*;
+
+#[derive(Debug)]
+/// A finalize-only compressor for use by services that totally order ID ranges.
+/// Tracks the finalized state of a document without a local session, and so cannot generate IDs.
+///
+/// # Example
+/// ```
+/// use distributed_id_allocator::compressor::{IdCompressor, SequencerCompressor};
+/// use id_types::CompressedId;
+///
+/// let mut client = IdCompressor::new();
+/// let mut sequencer = SequencerCompressor::new();
+///
+/// let id = client.generate_next_id();
+/// let id_range = client.take_next_range();
+/// _ = client.finalize_range(&id_range);
+/// _ = sequencer.finalize_range(&id_range);
+///
+/// let final_id = match client.normalize_to_op_space(id).unwrap().to_space() {
+///     CompressedId::Final(final_id) => final_id,
+///     CompressedId::Local(_) => unreachable!(),
+/// };
+/// assert_eq!(sequencer.decompress(final_id).unwrap(), client.decompress(id).unwrap());
+/// ```
+pub struct SequencerCompressor {
+    // Telemetry data.
+    telemetry_stats: TelemetryStats,
+    // Data structures:
+    finalized: FinalizedState,
+}
+
+impl SequencerCompressor {
+    /// Instantiates a new sequencer with no finalized state.
+    pub fn new() -> Self {
+        SequencerCompressor {
+            telemetry_stats: TelemetryStats::EMPTY,
+            finalized: FinalizedState::new(),
+        }
+    }
+
+    /// Returns the current sizing used for new clusters.
+    pub fn get_cluster_capacity(&self) -> u64 {
+        self.finalized.cluster_capacity
+    }
+
+    /// Updates the sizing used for new cluster creation.
+    /// This value should only be updated by ordered consensus, or divergence will occur.
+    /// Unlike `SequencerCompressor::apply_capacity_change`, the change is not recorded in the persisted capacity history.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The supplied cluster size must be a non-zero integer.
+    ///
+    pub fn set_cluster_capacity(
+        &mut self,
+        new_cluster_capacity: u64,
+    ) -> Result<(), AllocatorError> {
+        self.finalized.set_cluster_capacity(new_cluster_capacity)
+    }
+
+    /// Returns a [CapacityChange] op updating the sizing used for new cluster creation, for sequencing to all compressors.
+    /// The change does not take effect until it is applied via `apply_capacity_change`.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The supplied cluster size must be a non-zero integer.
+    pub fn propose_capacity_change(
+        &self,
+        new_cluster_capacity: u64,
+    ) -> Result<CapacityChange, AllocatorError> {
+        self.finalized.propose_capacity_change(new_cluster_capacity)
+    }
+
+    /// Applies a sequenced [CapacityChange], recording it in the persisted state of the document.
+    /// Changes must be applied in the same total order as finalized ranges by all compressors.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::CapacityChangeDiverged`
+    /// >   * The change was proposed against a cluster capacity other than the current one.
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The new cluster size must be a non-zero integer.
+    pub fn apply_capacity_change(&mut self, change: &CapacityChange) -> Result<(), AllocatorError> {
+        self.finalized.apply_capacity_change(change)
+    }
+
+    /// Returns the exclusive upper bound on the final IDs of this sequencer.
+    pub fn get_final_id_ceiling(&self) -> u64 {
+        self.finalized.final_id_ceiling
+    }
+
+    /// Updates the exclusive upper bound on the final IDs of this sequencer.
+    /// Ranges whose finalization would create or expand a cluster beyond the ceiling fail to finalize.
+    /// The ceiling is not persisted, and must be configured identically to that of every client compressor.
+    pub fn set_final_id_ceiling(&mut self, final_id_ceiling: u64) {
+        self.finalized.final_id_ceiling = final_id_ceiling;
+    }
+
+    /// Returns the largest cluster capacity that a range may request via its capacity hint.
+    pub fn get_max_capacity_hint(&self) -> u64 {
+        self.finalized.max_capacity_hint
+    }
+
+    /// Updates the largest cluster capacity that a range may request via its capacity hint.
+    /// Larger hints are clamped to this bound when finalized.
+    /// This value should only be updated by ordered consensus, or divergence will occur.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidClusterCapacity`
+    /// >   * The supplied bound must be a non-zero integer.
+    pub fn set_max_capacity_hint(&mut self, max_capacity_hint: u64) -> Result<(), AllocatorError> {
+        self.finalized.set_max_capacity_hint(max_capacity_hint)
+    }
+
+    /// Finalizes the supplied range of IDs.
+    /// Ranges must be finalized in the same total order as by all client compressors.
+    pub fn finalize_range(&mut self, id_range: &IdRange) -> Result<(), AllocatorError> {
+        self.finalize_range_with_outcome(id_range).map(|_| ())
+    }
+
+    /// Finalizes the supplied range of IDs, as `SequencerCompressor::finalize_range`.
+    /// Returns a [FinalizationOutcome] describing the IDs finalized and any clusters created or expanded.
+    pub fn finalize_range_with_outcome(
+        &mut self,
+        id_range: &IdRange,
+    ) -> Result<FinalizationOutcome, AllocatorError> {
+        let outcome = self.finalized.finalize_range(id_range)?;
+        self.telemetry_stats.record_finalization(&outcome, false);
+        Ok(outcome)
+    }
+
+    /// Checks whether the supplied range of IDs would finalize cleanly, without modifying the sequencer.
+    /// Returns a [PlannedFinalization] describing where the range would be placed and which final IDs it would receive.
+    ///
+    /// > # Errors
+    /// > * Any error that would be returned by `SequencerCompressor::finalize_range`.
+    pub fn check_range(&self, id_range: &IdRange) -> Result<PlannedFinalization, AllocatorError> {
+        self.finalized.plan_finalization(id_range)
+    }
+
+    /// Finalizes the supplied ranges of IDs in order, as `SequencerCompressor::finalize_range_with_outcome`.
+    /// The batch is atomic: if any range fails to finalize, no range in the batch is finalized.
+    /// Returns the [FinalizationOutcome] of each range, in order.
+    ///
+    /// > # Errors
+    /// > * [BatchFinalizationError]
+    /// >   * Contains the index of the first range that failed to finalize, and the reason it failed.
+    pub fn finalize_ranges(
+        &mut self,
+        id_ranges: &[IdRange],
+    ) -> Result<Vec<FinalizationOutcome>, BatchFinalizationError> {
+        let outcomes = self.finalized.finalize_ranges(id_ranges)?;
+        for outcome in &outcomes {
+            self.telemetry_stats.record_finalization(outcome, false);
+        }
+        Ok(outcomes)
+    }
+
+    /// Returns current sequencer state telemetry.
+    /// Intended for logging and analysis. A sequencer has no local session, and so its local counts are always zero.
+    pub fn get_telemetry_stats(&mut self) -> TelemetryStats {
+        let stats = self.telemetry_stats;
+        self.telemetry_stats = TelemetryStats::EMPTY;
+        stats
+    }
+
+    /// Decompresses a finalized final ID to its stable ID equivalent.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidFinalId`
+    /// >   * The provided [FinalId] has not been finalized.
+    pub fn decompress(&self, id: FinalId) -> Result<StableId, AllocatorError> {
+        let containing_cluster_ref = self
+            .finalized
+            .get_finalized_cluster(id)
+            .ok_or(AllocatorError::InvalidFinalId)?;
+        let containing_cluster = self
+            .finalized
+            .sessions
+            .deref_cluster(containing_cluster_ref);
+        let aligned_local = containing_cluster
+            .get_aligned_local(id)
+            .ok_or(AllocatorError::InvalidFinalId)?;
+        let session_id = self
+            .finalized
+            .sessions
+            .get_session_id(containing_cluster_ref.get_session_space_ref());
+        Ok(session_id + aligned_local)
+    }
+
+    /// Recompresses a stable ID to its finalized final ID equivalent.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::InvalidStableId`
+    /// >   * The provided [StableId] has not been finalized.
+    pub fn recompress(&self, id: StableId) -> Result<FinalId, AllocatorError> {
+        match self.finalized.sessions.get_containing_cluster(id) {
+            Some((cluster, _, corresponding_local))
+                if corresponding_local.to_generation_count()
+                    < cluster.base_local_id.to_generation_count() + cluster.count =>
+            {
+                cluster
+                    .get_allocated_final(corresponding_local)
+                    .ok_or(AllocatorError::InvalidStableId)
+            }
+            _ => Err(AllocatorError::InvalidStableId),
+        }
+    }
+
+    /// Checks that an op space ID sent by the originating session refers to an ID the originator could legitimately know about.
+    /// Locals must belong to the originator and have been finalized, and finals must have been finalized by some session.
+    /// Ranges sequenced along with an op must be finalized before its IDs are validated.
+    /// Returns the [IdStatus] of the provided [OpSpaceId], which is always `IdStatus::FinalizedLocal` or `IdStatus::Final`.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::NoTokenForSession`
+    /// >   * The provided [OpSpaceId] is a local and no IDs have been finalized by the originator.
+    /// > * `AllocatorError::InvalidOpSpaceId`
+    /// >   * The provided [OpSpaceId] has not been finalized.
+    pub fn validate_op_space_id(
+        &self,
+        id: OpSpaceId,
+        originator: SessionId,
+    ) -> Result<IdStatus, AllocatorError> {
+        match id.to_space() {
+            CompressedId::Local(local_id) => {
+                let originator_ref = *self
+                    .finalized
+                    .sessions
+                    .get(originator)
+                    .ok_or(AllocatorError::NoTokenForSession)?;
+                match self
+                    .finalized
+                    .try_convert_finalized_local(originator_ref, local_id)
+                {
+                    Some(final_id) => Ok(IdStatus::FinalizedLocal(originator, final_id)),
+                    None => Err(AllocatorError::InvalidOpSpaceId),
+                }
+            }
+            CompressedId::Final(final_id) => match self.finalized.get_finalized_cluster(final_id) {
+                Some(cluster_ref) => Ok(IdStatus::Final(
+                    self.finalized
+                        .sessions
+                        .get_session_id(cluster_ref.get_session_space_ref()),
+                )),
+                None => Err(AllocatorError::InvalidOpSpaceId),
+            },
+        }
+    }
+
+    /// Rewrites a batch of op space IDs sent by the originating session to their most-final form.
+    /// Finalized locals are converted to their final equivalents and finals are returned unchanged, so that
+    /// receiving clients need not normalize the originator's locals themselves.
+    /// The originator's ranges must be finalized, in total order, before the batch is rewritten.
+    ///
+    /// > # Errors
+    /// > * `AllocatorError::NoTokenForSession`
+    /// >   * The batch contains a local and no IDs have been finalized by the originator.
+    /// > * `AllocatorError::InvalidOpSpaceId`
+    /// >   * The batch contains a local that has not been finalized.
+    pub fn finalize_op_space_ids(
+        &self,
+        originator: SessionId,
+        ids: &[OpSpaceId],
+    ) -> Result<Vec<OpSpaceId>, AllocatorError> {
+        let mut originator_ref = None;
+        ids.iter()
+            .map(|&id| match id.to_space() {
+                CompressedId::Final(_) => Ok(id),
+                CompressedId::Local(local_id) => {
+                    let session_space_ref = match originator_ref {
+                        Some(session_space_ref) => session_space_ref,
+                        None => {
+                            let session_space_ref = *self
+                                .finalized
+                                .sessions
+                                .get(originator)
+                                .ok_or(AllocatorError::NoTokenForSession)?;
+                            originator_ref = Some(session_space_ref);
+                            session_space_ref
+                        }
+                    };
+                    self.finalized
+                        .try_convert_finalized_local(session_space_ref, local_id)
+                        .map(OpSpaceId::from)
+                        .ok_or(AllocatorError::InvalidOpSpaceId)
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a snapshot of the layout of every cluster in the document, in ascending base final ID order.
+    /// Intended for analysis of cluster capacity and fragmentation.
+    pub fn get_cluster_layout(&self) -> Vec<ClusterSnapshot> {
+        self.finalized.get_cluster_layout()
+    }
+
+    /// Returns a report of the final ID space reserved and used by the clusters of each session and of the document.
+    pub fn space_report(&self) -> SpaceReport {
+        self.finalized.space_report()
+    }
+
+    /// Returns a persistable form of the current state of this `SequencerCompressor`.
+    /// The serialized form is identical to that of an `IdCompressor` serialized without local state, and can be
+    /// rehydrated by either `SequencerCompressor::deserialize()` or `IdCompressor::deserialize()`.
+    pub fn serialize(&self) -> Vec<u8> {
+        persistence::v8::serialize_finalized(&self.finalized)
+    }
+
+    /// Rehydrates a serialized `SequencerCompressor`.
+    /// Accepts the serialized form of an `IdCompressor` (with or without local state), of which only finalized state is retained.
+    pub fn deserialize(bytes: &[u8]) -> Result<SequencerCompressor, DeserializationError> {
+        Ok(SequencerCompressor {
+            telemetry_stats: TelemetryStats::EMPTY,
+            finalized: persistence::deserialize_finalized(bytes)?,
+        })
+    }
+}
+
+impl Default for SequencerCompressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -36,6 +36,11 @@ impl FinalSpace {
         self.clusters.push(new_cluster_ref);
     }
 
+    // Removes the cluster with the greatest base final ID, returning a reference to it.
+    pub fn pop_cluster(&mut self) -> Option<ClusterRef> {
+        self.clusters.pop()
+    }
+
     // Searches the Final table for a cluster whose capacity would include the given Final.
     //   Does not guarantee that the Final has been generated.
     pub fn search(&self, target_final: FinalId, sessions: &Sessions) -> Option<ClusterRef> {
@@ -45,6 +45,16 @@ impl Sessions {
         })
     }
 
+    // Removes all sessions created after the first `session_count` sessions.
+    pub fn truncate(&mut self, session_count: usize) {
+        for index in session_count..self.session_list.len() {
+            let session_id = self.get_session_id(SessionSpaceRef { index });
+            _ = self.session_map.remove(&session_id);
+        }
+        self.session_list.truncate(session_count);
+        self.session_ids.truncate(session_count * size_of::<u128>());
+    }
+
     pub fn get_session_id(&self, session_space_ref: SessionSpaceRef) -> SessionId {
         let index = session_space_ref.index * size_of::<u128>();
         let bytes: [u8; 16] = self.session_ids[index..index + size_of::<u128>()]
@@ -84,6 +94,27 @@ impl Sessions {
             .cluster_chain[cluster_ref.cluster_chain_index]
     }
 
+    // Iterates all session spaces in the order their sessions were created.
+    pub fn get_session_spaces(&self) -> impl Iterator<Item = &SessionSpace> {
+        self.session_list.iter()
+    }
+
+    #[cfg(test)]
+    pub fn clear_range_bases(&mut self) {
+        self.session_list
+            .iter_mut()
+            .for_each(SessionSpace::clear_range_bases);
+    }
+
+    // Iterates all sessions sorted by their session ID (and therefore by their base stable ID).
+    pub fn iter_in_stable_order(&self) -> impl Iterator<Item = (SessionId, &SessionSpace)> {
+        self.session_map
+            .iter()
+            .map(|(&session_id, &session_space_ref)| {
+                (session_id, self.deref_session_space(session_space_ref))
+            })
+    }
+
     pub fn get_session_id_slice(&self) -> &[u8] {
         &self.session_ids
     }
@@ -186,6 +217,7 @@ impl Sessions {
             let (session_space_b, session_id_b) = session_space_b.unwrap();
             if session_id_a != session_id_b
                 || session_space_a.cluster_chain != session_space_b.cluster_chain
+                || session_space_a.range_bases != session_space_b.range_bases
             {
                 return false;
             }
@@ -222,19 +254,68 @@ impl std::fmt::Debug for Sessions {
 pub struct SessionSpace {
     // All clusters in the session space, sorted on LocalId.
     cluster_chain: Vec<IdCluster>,
+    // The first generation count of every range finalized by the session, in ascending order.
+    // Ranges finalized before the state was loaded from a format predating their persistence are absent.
+    range_bases: Vec<u64>,
 }
 
 impl SessionSpace {
     pub fn new() -> SessionSpace {
         SessionSpace {
             cluster_chain: Vec::new(),
+            range_bases: Vec::new(),
+        }
+    }
+
+    pub fn get_range_bases(&self) -> &[u64] {
+        &self.range_bases
+    }
+
+    // Records the first generation count of a range finalized after all previously recorded ranges.
+    pub fn push_range_base(&mut self, range_base: u64) {
+        debug_assert!(self.range_bases.last() < Some(&range_base));
+        self.range_bases.push(range_base);
+    }
+
+    // Removes the most recently recorded range base.
+    pub fn pop_range_base(&mut self) -> Option<u64> {
+        self.range_bases.pop()
+    }
+
+    #[cfg(test)]
+    pub fn clear_range_bases(&mut self) {
+        self.range_bases.clear();
+    }
+
+    // Returns the count of IDs finalized by the session.
+    pub fn get_finalized_count(&self) -> u64 {
+        match self.get_tail_cluster() {
+            None => 0,
+            Some(tail_cluster) => {
+                tail_cluster.base_local_id.to_generation_count() - 1 + tail_cluster.count
+            }
         }
     }
 
+    // Returns the generation count following the last ID of the finalized range beginning at `range_base`, if one was
+    // recorded. Finalized ranges are contiguous, so each ends where the next begins.
+    pub fn get_range_end(&self, range_base: u64) -> Option<u64> {
+        let index = self.range_bases.binary_search(&range_base).ok()?;
+        Some(match self.range_bases.get(index + 1) {
+            Some(&next_range_base) => next_range_base,
+            None => self.get_finalized_count() + 1,
+        })
+    }
+
     pub fn cluster_chain_is_empty(&self) -> bool {
         self.cluster_chain.is_empty()
     }
 
+    // Iterates all clusters in the session space, in ascending generation order.
+    pub fn get_clusters(&self) -> impl Iterator<Item = &IdCluster> {
+        self.cluster_chain.iter()
+    }
+
     pub fn get_tail_cluster(&self) -> Option<&IdCluster> {
         if self.cluster_chain.is_empty() {
             return None;
@@ -283,6 +364,11 @@ impl SessionSpace {
         }
     }
 
+    // Removes the cluster with the greatest base local ID.
+    pub fn pop_cluster(&mut self) -> Option<IdCluster> {
+        self.cluster_chain.pop()
+    }
+
     pub fn try_convert_to_final(
         &self,
         search_local: LocalId,
@@ -24,6 +24,20 @@ impl SessionSpaceNormalizer {
         self.leading_locals.push((base_local, count));
     }
 
+    // Removes the last `count` locals from the trailing range, which must contain at least that many locals.
+    pub fn remove_trailing_locals(&mut self, count: u64) {
+        let (_, last_count) = self.leading_locals.last_mut().unwrap();
+        debug_assert!(*last_count >= count, "Removed locals span multiple ranges.");
+        *last_count -= count;
+        if *last_count == 0 {
+            _ = self.leading_locals.pop();
+        }
+    }
+
+    pub fn get_ranges(&self) -> &[(LocalId, u64)] {
+        &self.leading_locals
+    }
+
     pub fn contains(&self, query: LocalId) -> bool {
         self.leading_locals
             .binary_search_by(|(current_local, current_count)| {
@@ -83,6 +97,21 @@ mod tests {
         assert!(session_space_normalizer.contains(local_id_from_id(-15)));
     }
 
+    #[test]
+    fn test_remove_trailing_locals() {
+        let mut session_space_normalizer = SessionSpaceNormalizer::new();
+        session_space_normalizer.add_local_range(local_id_from_id(-1), 2);
+        session_space_normalizer.add_local_range(local_id_from_id(-6), 3);
+        session_space_normalizer.remove_trailing_locals(2);
+        assert!(session_space_normalizer.contains(local_id_from_id(-6)));
+        assert!(!session_space_normalizer.contains(local_id_from_id(-7)));
+        session_space_normalizer.remove_trailing_locals(1);
+        assert_eq!(
+            session_space_normalizer.get_ranges(),
+            &[(local_id_from_id(-1), 2)]
+        );
+    }
+
     #[test]
     fn test_contains() {
         let mut session_space_normalizer = SessionSpaceNormalizer::new();
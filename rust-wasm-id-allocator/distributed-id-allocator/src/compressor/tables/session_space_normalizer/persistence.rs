@@ -1,10 +1,13 @@
 pub(crate) mod v1 {
+    #[cfg(test)]
+    use crate::compressor::persistence_utils::write_u64_to_vec;
     use crate::compressor::{
-        persistence_utils::{write_u64_to_vec, Deserializer},
+        persistence::DeserializationError, persistence_utils::Deserializer,
         tables::session_space_normalizer::SessionSpaceNormalizer,
     };
     use id_types::LocalId;
 
+    #[cfg(test)]
     pub fn serialize_normalizer(
         session_space_normalizer: &SessionSpaceNormalizer,
         bytes: &mut Vec<u8>,
@@ -22,23 +25,74 @@ pub(crate) mod v1 {
             });
     }
 
-    pub fn deserialize_normalizer(deserializer: &mut Deserializer) -> SessionSpaceNormalizer {
-        let len = deserializer.take_u64();
+    pub fn deserialize_normalizer(
+        deserializer: &mut Deserializer,
+    ) -> Result<SessionSpaceNormalizer, DeserializationError> {
+        let len = deserializer.take_u64()?;
         let mut normalizer = SessionSpaceNormalizer::new();
         for _ in 0..len {
-            let gen_count = deserializer.take_u64();
-            let count = deserializer.take_u64();
+            let gen_count = deserializer.take_u64()?;
+            let count = deserializer.take_u64()?;
+            // Generation counts are 1-based and ranges are non-empty
+            if gen_count == 0 || gen_count > i64::MAX as u64 || count == 0 {
+                return Err(DeserializationError::MalformedInput);
+            }
             normalizer
                 .leading_locals
                 .push((LocalId::from_generation_count(gen_count), count));
         }
-        normalizer
+        Ok(normalizer)
+    }
+}
+
+pub(crate) mod v2 {
+    use crate::compressor::{
+        persistence::DeserializationError,
+        persistence_utils::{write_varint_to_vec, Deserializer},
+        tables::session_space_normalizer::SessionSpaceNormalizer,
+    };
+    use id_types::LocalId;
+
+    pub fn serialize_normalizer(
+        session_space_normalizer: &SessionSpaceNormalizer,
+        bytes: &mut Vec<u8>,
+    ) {
+        // Layout:
+        // Len (varint)
+        // (Local as gen_count (varint), count (varint))[]
+        write_varint_to_vec(bytes, session_space_normalizer.leading_locals.len() as u64);
+        session_space_normalizer
+            .leading_locals
+            .iter()
+            .for_each(|(local, count)| {
+                write_varint_to_vec(bytes, local.to_generation_count());
+                write_varint_to_vec(bytes, *count);
+            });
+    }
+
+    pub fn deserialize_normalizer(
+        deserializer: &mut Deserializer,
+    ) -> Result<SessionSpaceNormalizer, DeserializationError> {
+        let len = deserializer.take_varint()?;
+        let mut normalizer = SessionSpaceNormalizer::new();
+        for _ in 0..len {
+            let gen_count = deserializer.take_varint()?;
+            let count = deserializer.take_varint()?;
+            // Generation counts are 1-based and ranges are non-empty
+            if gen_count == 0 || gen_count > i64::MAX as u64 || count == 0 {
+                return Err(DeserializationError::MalformedInput);
+            }
+            normalizer
+                .leading_locals
+                .push((LocalId::from_generation_count(gen_count), count));
+        }
+        Ok(normalizer)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::v1::{deserialize_normalizer, serialize_normalizer};
+    use super::{v1, v2};
     use crate::compressor::{
         persistence_utils::Deserializer, tables::session_space_normalizer::SessionSpaceNormalizer,
     };
@@ -52,8 +106,30 @@ mod tests {
         session_space_normalizer.add_local_range(local_id_from_id(-15), 1);
 
         let mut bytes: Vec<u8> = Vec::new();
-        serialize_normalizer(&session_space_normalizer, &mut bytes);
-        let normalizer = deserialize_normalizer(&mut Deserializer::new(&bytes));
+        v1::serialize_normalizer(&session_space_normalizer, &mut bytes);
+        let normalizer = v1::deserialize_normalizer(&mut Deserializer::new(&bytes)).unwrap();
+        assert!(normalizer.eq(&session_space_normalizer));
+
+        let mut bytes_v2: Vec<u8> = Vec::new();
+        v2::serialize_normalizer(&session_space_normalizer, &mut bytes_v2);
+        let normalizer = v2::deserialize_normalizer(&mut Deserializer::new(&bytes_v2)).unwrap();
         assert!(normalizer.eq(&session_space_normalizer));
+        assert!(bytes_v2.len() < bytes.len());
+    }
+
+    #[test]
+    fn test_deserialize_truncated_normalizer() {
+        let mut session_space_normalizer = SessionSpaceNormalizer::new();
+        session_space_normalizer.add_local_range(local_id_from_id(-1), 2);
+        let mut bytes: Vec<u8> = Vec::new();
+        v1::serialize_normalizer(&session_space_normalizer, &mut bytes);
+        for len in 0..bytes.len() {
+            assert!(v1::deserialize_normalizer(&mut Deserializer::new(&bytes[..len])).is_err());
+        }
+        let mut bytes: Vec<u8> = Vec::new();
+        v2::serialize_normalizer(&session_space_normalizer, &mut bytes);
+        for len in 0..bytes.len() {
+            assert!(v2::deserialize_normalizer(&mut Deserializer::new(&bytes[..len])).is_err());
+        }
     }
 }
@@ -0,0 +1,452 @@
+use super::tables::session_space::IdCluster;
+use super::IdCompressor;
+use id_types::{FinalId, LocalId, SessionId, StableId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The result of checking the structural invariants of an `IdCompressor`.
+pub struct ValidationReport {
+    /// All invariant violations found, in the order they were checked.
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns true if no invariant violations were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A single invariant violation found by `IdCompressor::validate`.
+pub enum ValidationIssue {
+    /// The cluster capacity is zero.
+    ZeroClusterCapacity,
+    /// A cluster has a capacity of zero.
+    /// Contains `(owning session, base final ID of the cluster)`.
+    EmptyCluster(SessionId, FinalId),
+    /// A cluster has finalized more IDs than its capacity.
+    /// Contains `(owning session, base final ID of the cluster, capacity, count)`.
+    ClusterOverfilled(SessionId, FinalId, u64, u64),
+    /// The stable IDs allocated to two sessions overlap.
+    /// Contains `(lower session, colliding session)`.
+    SessionCollision(SessionId, SessionId),
+    /// A session space normalizer range is out of order or extends past the generated ID count.
+    /// Contains `(first local of the range, count of IDs in the range)`.
+    NormalizerRangeOutOfBounds(LocalId, u64),
+    /// The base of the next range lies beyond the IDs generated by the local session.
+    /// Contains `(next range base generation count, generated ID count)`.
+    NextRangeBaseOutOfBounds(u64, u64),
+    /// The local session owns clusters but has not generated any IDs.
+    /// Contains the number of clusters owned by the local session.
+    LocalClustersWithoutIds(usize),
+    /// The local session has finalized IDs that it has not yet taken in a range.
+    /// Contains `(generation count of the last finalized local ID, next range base generation count)`.
+    FinalizedBeyondNextRange(u64, u64),
+    /// A pending range is empty, out of order or extends past the base of the next range.
+    /// Contains `(first generation count of the range, count of IDs in the range)`.
+    PendingRangeOutOfBounds(u64, u64),
+    /// The cluster capacity differs from the capacity set by the most recent recorded capacity change.
+    /// Contains `(most recently recorded capacity, cluster capacity)`.
+    CapacityChangeUnrecorded(u64, u64),
+}
+
+impl IdCompressor {
+    /// Checks the structural invariants of this compressor, returning a report of all violations found.
+    /// Intended for use on compressors deserialized from untrusted sources; compressors built solely through
+    /// this API are always valid.
+    pub fn validate(&self) -> ValidationReport {
+        let mut issues = Vec::new();
+        self.validate_clusters(&mut issues);
+        self.validate_capacity_history(&mut issues);
+        self.validate_session_collisions(&mut issues);
+        self.validate_local_state(&mut issues);
+        ValidationReport { issues }
+    }
+
+    fn validate_clusters(&self, issues: &mut Vec<ValidationIssue>) {
+        if self.finalized.cluster_capacity == 0 {
+            issues.push(ValidationIssue::ZeroClusterCapacity);
+        }
+        for (cluster, cluster_ref) in self
+            .finalized
+            .final_space
+            .get_clusters(&self.finalized.sessions)
+        {
+            let session_id = self
+                .finalized
+                .sessions
+                .get_session_id(cluster_ref.get_session_space_ref());
+            if cluster.capacity == 0 {
+                issues.push(ValidationIssue::EmptyCluster(
+                    session_id,
+                    cluster.base_final_id,
+                ));
+            }
+            if cluster.count > cluster.capacity {
+                issues.push(ValidationIssue::ClusterOverfilled(
+                    session_id,
+                    cluster.base_final_id,
+                    cluster.capacity,
+                    cluster.count,
+                ));
+            }
+        }
+    }
+
+    fn validate_capacity_history(&self, issues: &mut Vec<ValidationIssue>) {
+        if let Some(&(_, recorded_capacity)) = self.finalized.capacity_history.last() {
+            if recorded_capacity != self.finalized.cluster_capacity {
+                issues.push(ValidationIssue::CapacityChangeUnrecorded(
+                    recorded_capacity,
+                    self.finalized.cluster_capacity,
+                ));
+            }
+        }
+    }
+
+    fn validate_session_collisions(&self, issues: &mut Vec<ValidationIssue>) {
+        // Sessions are visited in stable ID order, so each session's base need only be compared
+        // against the greatest stable ID allocated by any session before it.
+        let mut greatest_allocated: Option<(SessionId, u128)> = None;
+        for (session_id, session_space) in self.finalized.sessions.iter_in_stable_order() {
+            let tail_cluster = match session_space.get_tail_cluster() {
+                None => continue,
+                Some(tail_cluster) => tail_cluster,
+            };
+            let session_base = u128::from(StableId::from(session_id));
+            if let Some((lower_session_id, lower_max)) = greatest_allocated {
+                if session_base <= lower_max {
+                    issues.push(ValidationIssue::SessionCollision(
+                        lower_session_id,
+                        session_id,
+                    ));
+                }
+            }
+            if let Some(session_max) = get_max_allocated_stable(session_base, tail_cluster) {
+                match greatest_allocated {
+                    Some((_, lower_max)) if lower_max >= session_max => {}
+                    _ => greatest_allocated = Some((session_id, session_max)),
+                }
+            }
+        }
+    }
+
+    fn validate_local_state(&self, issues: &mut Vec<ValidationIssue>) {
+        // Generation counts are 1-based, so a next range base of zero is itself out of bounds.
+        let taken_count = self
+            .next_range_base_generation_count
+            .checked_sub(1)
+            .unwrap_or(u64::MAX);
+        if taken_count > self.generated_id_count {
+            issues.push(ValidationIssue::NextRangeBaseOutOfBounds(
+                self.next_range_base_generation_count,
+                self.generated_id_count,
+            ));
+        }
+
+        let local_session_space = self.get_local_session_space();
+        if self.generated_id_count == 0 && !local_session_space.cluster_chain_is_empty() {
+            issues.push(ValidationIssue::LocalClustersWithoutIds(
+                local_session_space.get_clusters().count(),
+            ));
+        }
+
+        if let Some(tail_cluster) = local_session_space.get_tail_cluster() {
+            if tail_cluster.count > 0 {
+                let last_finalized = (tail_cluster.base_local_id.to_generation_count() - 1)
+                    .saturating_add(tail_cluster.count);
+                if last_finalized > taken_count {
+                    issues.push(ValidationIssue::FinalizedBeyondNextRange(
+                        last_finalized,
+                        self.next_range_base_generation_count,
+                    ));
+                }
+            }
+        }
+
+        let mut previous_last = 0;
+        for &(base_local, count) in self.session_space_normalizer.get_ranges() {
+            let base_generation_count = base_local.to_generation_count();
+            let last_generation_count = (base_generation_count - 1).saturating_add(count);
+            if base_generation_count <= previous_last
+                || last_generation_count > self.generated_id_count
+            {
+                issues.push(ValidationIssue::NormalizerRangeOutOfBounds(
+                    base_local, count,
+                ));
+            }
+            previous_last = previous_last.max(last_generation_count);
+        }
+
+        // Pending ranges must be ascending, disjoint and taken before the next range base.
+        let mut next_base = 1;
+        for &(base, count, _) in &self.pending_ranges {
+            match base.checked_add(count) {
+                Some(end)
+                    if base >= next_base
+                        && count > 0
+                        && end <= self.next_range_base_generation_count =>
+                {
+                    next_base = end;
+                }
+                _ => issues.push(ValidationIssue::PendingRangeOutOfBounds(base, count)),
+            }
+        }
+    }
+}
+
+// Returns the greatest stable ID allocated to the session, or None if it cannot be represented.
+fn get_max_allocated_stable(session_base: u128, tail_cluster: &IdCluster) -> Option<u128> {
+    let allocated_count = (tail_cluster.base_local_id.to_generation_count() - 1)
+        .checked_add(tail_cluster.capacity)?
+        .checked_sub(1)?;
+    session_base.checked_add(allocated_count as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compressor::{persistence::v2, DeserializationError, IdRange};
+    use id_types::{final_id::final_id_from_id, local_id::local_id_from_id};
+
+    #[test]
+    fn test_constructed_compressor_is_valid() {
+        let mut compressor = IdCompressor::new();
+        let mut compressor_2 = IdCompressor::new();
+        for _ in 0..5 {
+            _ = compressor.generate_next_id();
+            _ = compressor_2.generate_next_id();
+        }
+        let range = compressor.take_next_range();
+        _ = compressor.finalize_range(&range);
+        let range = compressor_2.take_next_range();
+        _ = compressor.finalize_range(&range);
+        _ = compressor.generate_next_id();
+        assert!(compressor.validate().is_valid());
+        let serialized = compressor.serialize(true);
+        let deserialized = IdCompressor::deserialize_with_validation(&serialized, SessionId::new);
+        assert!(deserialized.is_ok());
+    }
+
+    #[test]
+    fn test_invalid_local_state() {
+        let mut compressor = IdCompressor::new();
+        _ = compressor.generate_next_id();
+        let range = compressor.take_next_range();
+        _ = compressor.finalize_range(&range);
+        compressor.generated_id_count = 0;
+        compressor.next_range_base_generation_count = 1;
+        compressor
+            .session_space_normalizer
+            .add_local_range(local_id_from_id(-3), 1);
+        let report = compressor.validate();
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue::LocalClustersWithoutIds(1),
+                ValidationIssue::FinalizedBeyondNextRange(1, 1),
+                ValidationIssue::NormalizerRangeOutOfBounds(local_id_from_id(-1), 1),
+                ValidationIssue::NormalizerRangeOutOfBounds(local_id_from_id(-3), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_clusters() {
+        let mut compressor = IdCompressor::new();
+        let session_a =
+            SessionId::from_uuid_string("748540ca-b7c5-4c99-83ff-c1b8e02c09d6").unwrap();
+        let session_b =
+            SessionId::from_uuid_string("748540ca-b7c5-4c99-83ff-c1b8e02c09d7").unwrap();
+        for session_id in [session_a, session_b] {
+            let session_space_ref = compressor.finalized.sessions.get_or_create(session_id);
+            let cluster_ref = compressor
+                .finalized
+                .sessions
+                .deref_session_space_mut(session_space_ref)
+                .add_cluster(
+                    session_space_ref,
+                    IdCluster {
+                        base_final_id: compressor.finalized.final_id_limit,
+                        base_local_id: local_id_from_id(-1),
+                        capacity: 2,
+                        count: 3,
+                    },
+                );
+            compressor
+                .finalized
+                .final_space
+                .add_cluster(cluster_ref, &compressor.finalized.sessions);
+            compressor.finalized.final_id_limit = compressor.finalized.final_id_limit + 2;
+        }
+        let report = compressor.validate();
+        assert!(!report.is_valid());
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue::ClusterOverfilled(session_a, final_id_from_id(0), 2, 3),
+                ValidationIssue::ClusterOverfilled(session_b, final_id_from_id(2), 2, 3),
+                ValidationIssue::SessionCollision(session_a, session_b),
+            ]
+        );
+        // Overfilled clusters are rejected as malformed when deserialized, so only the collision is persisted
+        for cluster_ref in compressor
+            .finalized
+            .final_space
+            .get_clusters(&compressor.finalized.sessions)
+            .map(|(_, cluster_ref)| cluster_ref)
+            .collect::<Vec<_>>()
+        {
+            compressor
+                .finalized
+                .sessions
+                .deref_cluster_mut(cluster_ref)
+                .count = 2;
+        }
+        let serialized = compressor.serialize(false);
+        assert_eq!(
+            IdCompressor::deserialize_with_validation(&serialized, SessionId::new).err(),
+            Some(DeserializationError::FailedValidation(ValidationReport {
+                issues: vec![ValidationIssue::SessionCollision(session_a, session_b)],
+            }))
+        );
+    }
+
+    #[test]
+    fn test_overfilled_snapshot_does_not_underflow() {
+        let mut compressor = IdCompressor::new();
+        let session_id = SessionId::new();
+        let session_space_ref = compressor.finalized.sessions.get_or_create(session_id);
+        let cluster_ref = compressor
+            .finalized
+            .sessions
+            .deref_session_space_mut(session_space_ref)
+            .add_cluster(
+                session_space_ref,
+                IdCluster {
+                    base_final_id: final_id_from_id(0),
+                    base_local_id: local_id_from_id(-1),
+                    capacity: 2,
+                    count: 3,
+                },
+            );
+        compressor
+            .finalized
+            .final_space
+            .add_cluster(cluster_ref, &compressor.finalized.sessions);
+        compressor.finalized.final_id_limit = final_id_from_id(2);
+        assert_eq!(
+            compressor.validate().issues,
+            vec![ValidationIssue::ClusterOverfilled(
+                session_id,
+                final_id_from_id(0),
+                2,
+                3
+            )]
+        );
+
+        let report = compressor.space_report();
+        assert_eq!((report.capacity, report.count), (2, 3));
+        assert_eq!(report.unused_count, 0);
+
+        // The overfilled cluster is treated as full rather than underflowing its remaining capacity
+        compressor
+            .finalize_range(&IdRange {
+                id: session_id,
+                range: Some((4, 1)),
+                capacity_hint: None,
+            })
+            .unwrap();
+    }
+    #[test]
+    fn test_invalid_capacity_and_pending_ranges() {
+        let mut compressor = IdCompressor::new();
+        for _ in 0..3 {
+            _ = compressor.generate_next_id();
+        }
+        _ = compressor.take_next_range();
+        compressor.finalized.cluster_capacity = 0;
+        compressor.pending_ranges = vec![(1, 0, None), (2, 2, None), (3, 2, None)];
+        assert_eq!(
+            compressor.validate().issues,
+            vec![
+                ValidationIssue::ZeroClusterCapacity,
+                ValidationIssue::PendingRangeOutOfBounds(1, 0),
+                ValidationIssue::PendingRangeOutOfBounds(3, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validated_snapshots_roundtrip() {
+        let mut compressor = IdCompressor::new();
+        let mut compressor_2 = IdCompressor::new();
+        _ = compressor.set_cluster_capacity(3);
+        for _ in 0..2 {
+            for _ in 0..2 {
+                _ = compressor.generate_next_id();
+                _ = compressor_2.generate_next_id();
+            }
+            let range = compressor.take_next_range();
+            _ = compressor.finalize_range(&range);
+            let range = compressor_2.take_next_range();
+            _ = compressor.finalize_range(&range);
+        }
+        _ = compressor.generate_next_id();
+
+        // A local session owning an unfilled cluster without having generated any IDs, which previously passed validation
+        // and then failed to serialize. It is written with a generated ID count of one, which is then zeroed.
+        let session_id = SessionId::new();
+        let mut resumed_compressor = IdCompressor::new_with_session_id(session_id);
+        _ = resumed_compressor.finalize_range(&IdRange {
+            id: session_id,
+            range: Some((1, 1)),
+            capacity_hint: None,
+        });
+        let cluster_ref = resumed_compressor
+            .finalized
+            .final_space
+            .get_clusters(&resumed_compressor.finalized.sessions)
+            .map(|(_, cluster_ref)| cluster_ref)
+            .next()
+            .unwrap();
+        resumed_compressor
+            .finalized
+            .sessions
+            .deref_cluster_mut(cluster_ref)
+            .count = 0;
+        resumed_compressor.generated_id_count = 1;
+        let mut resumed = v2::serialize_with_local(&resumed_compressor);
+        // The generated ID count follows the version, local state flag and session UUID
+        let generated_id_count_index = 4 + 1 + 16;
+        assert_eq!(resumed[generated_id_count_index], 1);
+        resumed[generated_id_count_index] = 0;
+
+        // Overwrites each byte (following the version) of an unchecksummed format with a selection of values, and checks
+        // that every snapshot passing validation can itself be serialized and deserialized.
+        for serialized in [
+            v2::serialize(&compressor),
+            v2::serialize_with_local(&compressor),
+            resumed,
+        ] {
+            let mutations = (4..serialized.len()).flat_map(|index| {
+                [0, 1, 2, 0x7f, 0x80, 0xff].map(|value| {
+                    let mut mutated = serialized.clone();
+                    mutated[index] = value;
+                    mutated
+                })
+            });
+            for mutated in std::iter::once(serialized.clone()).chain(mutations) {
+                if let Ok(deserialized) =
+                    IdCompressor::deserialize_with_validation(&mutated, SessionId::new)
+                {
+                    for with_local in [false, true] {
+                        let roundtripped =
+                            IdCompressor::deserialize(&deserialized.serialize(with_local)).unwrap();
+                        assert!(deserialized.equals_test_only(&roundtripped, with_local));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -2,6 +2,8 @@ mod compressor_test_utils;
 use compressor_test_utils::*;
 
 use distributed_id_allocator::compressor::*;
+use id_types::final_id::get_id_from_final_id;
+use id_types::session_id::session_id_from_stable_id;
 use id_types::*;
 use std::collections::{BTreeSet, HashSet};
 use uuid::Uuid;
@@ -66,6 +68,167 @@ fn test_detects_invalid_cluster_capacities() {
     ));
 }
 
+#[test]
+fn test_capacity_change() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_2 = IdCompressor::new();
+    assert!(matches!(
+        compressor.propose_capacity_change(0).unwrap_err(),
+        AllocatorError::InvalidClusterCapacity
+    ));
+    let change = compressor.propose_capacity_change(5).unwrap();
+    // Proposing does not change the capacity
+    assert_eq!(
+        compressor.get_cluster_capacity(),
+        IdCompressor::get_default_cluster_capacity()
+    );
+    let change = CapacityChange::deserialize(&change.serialize()).unwrap();
+    for compressor in [&mut compressor, &mut compressor_2] {
+        compressor.apply_capacity_change(&change).unwrap();
+        assert_eq!(compressor.get_cluster_capacity(), 5);
+    }
+
+    // A replayed or concurrently proposed change no longer applies
+    assert!(matches!(
+        compressor_2.apply_capacity_change(&change).unwrap_err(),
+        AllocatorError::CapacityChangeDiverged
+    ));
+
+    // Late joiners receive the change along with the rest of the finalized state
+    _ = compressor.generate_next_id();
+    let range = compressor.take_next_range();
+    _ = compressor.finalize_range(&range);
+    let mut joiner = IdCompressor::deserialize(&compressor.serialize(false)).unwrap();
+    assert_eq!(joiner.get_cluster_capacity(), 5);
+    let change = compressor.propose_capacity_change(10).unwrap();
+    for compressor in [&mut compressor, &mut joiner] {
+        compressor.apply_capacity_change(&change).unwrap();
+    }
+    assert_eq!(compressor.serialize(false), joiner.serialize(false));
+    assert!(joiner.validate().is_valid());
+
+    // Unsequenced changes are not recorded, and so are detected as diverging from the recorded history
+    joiner.set_cluster_capacity(20).unwrap();
+    assert_eq!(
+        joiner.validate().issues,
+        vec![ValidationIssue::CapacityChangeUnrecorded(10, 20)]
+    );
+    joiner.set_cluster_capacity(10).unwrap();
+    assert_eq!(compressor.serialize(false), joiner.serialize(false));
+}
+
+#[test]
+fn test_capacity_hint() {
+    let mut importer = IdCompressor::new();
+    let mut editor = IdCompressor::new();
+    assert!(matches!(
+        importer.set_capacity_hint(Some(0)).unwrap_err(),
+        AllocatorError::InvalidClusterCapacity
+    ));
+    for compressor in [&mut importer, &mut editor] {
+        compressor.set_max_capacity_hint(1000).unwrap();
+    }
+    importer.set_capacity_hint(Some(5000)).unwrap();
+    editor.set_capacity_hint(Some(2)).unwrap();
+    let mut ranges = Vec::new();
+    for compressor in [&mut importer, &mut editor] {
+        _ = compressor.generate_next_id();
+        ranges.push(compressor.take_next_range());
+    }
+    assert_eq!(ranges[0].capacity_hint, Some(5000));
+    for range in &ranges {
+        for compressor in [&mut importer, &mut editor] {
+            compressor.finalize_range(range).unwrap();
+        }
+    }
+    // Hints are honored identically by every compressor, clamped to the maximum
+    assert_eq!(importer.get_cluster_layout(), editor.get_cluster_layout());
+    let capacities: Vec<u64> = importer
+        .get_cluster_layout()
+        .iter()
+        .map(|cluster| cluster.capacity)
+        .collect();
+    assert_eq!(capacities, vec![1001, 3]);
+
+    let malformed_range = IdRange {
+        id: editor.get_local_session_id(),
+        range: Some((2, 1)),
+        capacity_hint: Some(0),
+    };
+    assert!(matches!(
+        importer.finalize_range(&malformed_range).unwrap_err(),
+        AllocatorError::MalformedIdRange
+    ));
+}
+
+#[test]
+fn test_final_id_ceiling() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_2 = IdCompressor::new();
+    let mut sequencer = SequencerCompressor::new();
+    assert_eq!(compressor.get_final_id_ceiling(), u64::MAX);
+    compressor.set_cluster_capacity(5).unwrap();
+    compressor_2.set_cluster_capacity(5).unwrap();
+    sequencer.set_cluster_capacity(5).unwrap();
+    compressor.set_final_id_ceiling(8);
+    compressor_2.set_final_id_ceiling(8);
+    sequencer.set_final_id_ceiling(8);
+
+    // Generation counts must remain below the ceiling
+    _ = compressor.generate_n_ids(6);
+    assert!(matches!(
+        compressor.try_generate_n_ids(2).unwrap_err(),
+        AllocatorError::FinalIdSpaceExhausted
+    ));
+    _ = compressor.try_generate_next_id().unwrap();
+    assert!(matches!(
+        compressor.try_generate_next_id().unwrap_err(),
+        AllocatorError::FinalIdSpaceExhausted
+    ));
+
+    // The first cluster reserves 7 + 5 final IDs, which exceeds the ceiling
+    let range = compressor.take_next_range();
+    assert!(matches!(
+        sequencer.finalize_range(&range).unwrap_err(),
+        AllocatorError::FinalIdSpaceExhausted
+    ));
+    _ = compressor_2.generate_next_id();
+    let range_2 = compressor_2.take_next_range();
+    assert!(sequencer.finalize_range(&range_2).is_ok());
+    assert!(compressor.finalize_range(&range_2).is_ok());
+    assert!(compressor_2.finalize_range(&range_2).is_ok());
+
+    // Ghost allocations are bounded by the ceiling as well
+    let ghost_session = SessionId::new();
+    compressor_2.begin_ghost_session(ghost_session).unwrap();
+    _ = compressor_2.try_generate_n_ids(2).unwrap();
+    assert!(matches!(
+        compressor_2.try_generate_next_id().unwrap_err(),
+        AllocatorError::FinalIdSpaceExhausted
+    ));
+}
+
+#[test]
+fn test_stable_id_overflow() {
+    // 15 IDs below the top of the UUID space
+    let session_id = SessionId::from_uuid_string("ffffffff-ffff-4fff-bfff-fffffffffff0").unwrap();
+    let mut compressor = IdCompressor::new_with_session_id(session_id);
+    let ids: Vec<SessionSpaceId> = (0..17).map(|_| compressor.generate_next_id()).collect();
+    assert_eq!(
+        String::from(compressor.decompress(ids[15]).unwrap()),
+        "ffffffff-ffff-4fff-bfff-ffffffffffff"
+    );
+    assert!(matches!(
+        compressor.decompress(ids[16]).unwrap_err(),
+        AllocatorError::StableIdOverflow
+    ));
+    let range = compressor.take_next_range();
+    assert!(matches!(
+        compressor.finalize_range(&range).unwrap_err(),
+        AllocatorError::StableIdOverflow
+    ));
+}
+
 #[test]
 fn test_new_with_session_id() {
     let session_id = SessionId::new();
@@ -305,6 +468,225 @@ fn test_unique_eager_finals_with_outstanding_locals_after_expansion() {
     assert_eq!(stable_ids.len(), 7);
 }
 
+#[test]
+fn test_bulk_generation_matches_single_generation() {
+    let session_id = SessionId::new();
+    let mut compressor_bulk = IdCompressor::new_with_session_id(session_id);
+    let mut compressor_single = IdCompressor::new_with_session_id(session_id);
+    for compressor in [&mut compressor_bulk, &mut compressor_single] {
+        _ = compressor.set_cluster_capacity(5);
+        generate_n_ids(compressor, 2);
+        finalize_next_range(compressor);
+        _ = compressor.get_telemetry_stats();
+    }
+
+    // Spans the remaining eager finals in the tail cluster and overflows to locals
+    let generated = compressor_bulk.generate_n_ids(8);
+    let single_ids = generate_n_ids(&mut compressor_single, 8);
+    assert_eq!(generated.count(), 8);
+    assert_eq!(generated.eager_finals.unwrap().1, 5);
+    assert_eq!(generated.locals.unwrap().1, 3);
+    assert_eq!(
+        generated.iter().collect::<Vec<SessionSpaceId>>(),
+        single_ids
+    );
+
+    let stats_bulk = compressor_bulk.get_telemetry_stats();
+    let stats_single = compressor_single.get_telemetry_stats();
+    assert_eq!(stats_bulk.eager_final_count, stats_single.eager_final_count);
+    assert_eq!(stats_bulk.local_id_count, stats_single.local_id_count);
+    assert!(compressor_bulk.equals_test_only(&compressor_single, true));
+    for id in generated.iter() {
+        assert_eq!(
+            compressor_bulk.decompress(id).unwrap(),
+            compressor_single.decompress(id).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_bulk_generation_without_cluster() {
+    let mut compressor = IdCompressor::new();
+    let empty = compressor.generate_n_ids(0);
+    assert_eq!(empty.count(), 0);
+    assert!(empty.eager_finals.is_none() && empty.locals.is_none());
+
+    let generated = compressor.generate_n_ids(3);
+    assert!(generated.eager_finals.is_none());
+    assert_eq!(generated.locals, Some((SessionSpaceId::from_id(-1), 3)));
+    assert_eq!(compressor.generate_next_id(), SessionSpaceId::from_id(-4));
+    assert_eq!(compressor.take_next_range().range, Some((1, 4)));
+}
+
+#[test]
+fn test_ghost_session_allocates_identical_finals() {
+    let mut compressor_a = IdCompressor::new();
+    let mut compressor_b = IdCompressor::new();
+    _ = compressor_a.set_cluster_capacity(5);
+    _ = compressor_b.set_cluster_capacity(5);
+    generate_n_ids(&mut compressor_a, 2);
+    let range_a = compressor_a.take_next_range();
+    _ = compressor_a.finalize_range(&range_a);
+    _ = compressor_b.finalize_range(&range_a);
+
+    let ghost_session_id = SessionId::new();
+    let mut ghost_ids = Vec::new();
+    for compressor in [&mut compressor_a, &mut compressor_b] {
+        assert!(compressor.begin_ghost_session(ghost_session_id).is_ok());
+        ghost_ids.push(generate_n_ids(compressor, 3));
+        compressor.end_ghost_session();
+    }
+    assert_eq!(ghost_ids[0], ghost_ids[1]);
+    for (offset, id) in ghost_ids[0].iter().enumerate() {
+        assert!(id.is_final());
+        assert_eq!(
+            compressor_b.decompress(*id).unwrap(),
+            StableId::from(ghost_session_id) + offset as u64
+        );
+    }
+
+    // Ghost IDs create no locals or outstanding ranges
+    assert!(compressor_b.take_next_range().range.is_none());
+    assert!(compressor_a.equals_test_only(&compressor_b, false));
+    assert!(compressor_a.generate_next_id().is_final());
+}
+
+#[test]
+fn test_ghost_session_bulk_generation() {
+    let ghost_session_id = SessionId::new();
+    let local_session_id = SessionId::new();
+    let mut compressor_bulk = IdCompressor::new_with_session_id(local_session_id);
+    let mut compressor_single = IdCompressor::new_with_session_id(local_session_id);
+    for compressor in [&mut compressor_bulk, &mut compressor_single] {
+        generate_n_ids(compressor, 1);
+        finalize_next_range(compressor);
+        assert!(compressor.begin_ghost_session(ghost_session_id).is_ok());
+    }
+    let generated = compressor_bulk.generate_n_ids(4);
+    assert!(generated.locals.is_none());
+    assert_eq!(
+        generated.iter().collect::<Vec<SessionSpaceId>>(),
+        generate_n_ids(&mut compressor_single, 4)
+    );
+    assert!(compressor_bulk.equals_test_only(&compressor_single, false));
+}
+
+#[test]
+fn test_ghost_session_errors() {
+    let mut compressor = IdCompressor::new();
+    assert!(matches!(
+        compressor
+            .begin_ghost_session(compressor.get_local_session_id())
+            .unwrap_err(),
+        AllocatorError::InvalidGhostSession
+    ));
+    assert!(compressor.begin_ghost_session(SessionId::new()).is_ok());
+    assert!(matches!(
+        compressor
+            .begin_ghost_session(SessionId::new())
+            .unwrap_err(),
+        AllocatorError::InvalidGhostSession
+    ));
+    compressor.end_ghost_session();
+    assert!(compressor.generate_next_id().is_local());
+
+    // Sessions that already have IDs cannot be ghosted
+    let mut remote_compressor = IdCompressor::new();
+    generate_n_ids(&mut remote_compressor, 1);
+    let range = remote_compressor.take_next_range();
+    _ = compressor.finalize_range(&range);
+    assert!(matches!(
+        compressor.begin_ghost_session(range.id).unwrap_err(),
+        AllocatorError::InvalidGhostSession
+    ));
+}
+
+#[test]
+fn test_ghost_session_collisions() {
+    let session_id = SessionId::from_uuid_string("748540ca-b7c5-4c99-83ff-c1b8e02c09d6").unwrap();
+    let mut remote_compressor = IdCompressor::new_with_session_id(session_id);
+    generate_n_ids(&mut remote_compressor, 2);
+    let range = remote_compressor.take_next_range();
+    let session_base = StableId::from(session_id);
+    for ghost_base in [session_base - 2, session_base + 2] {
+        let mut compressor = IdCompressor::new();
+        _ = compressor.set_cluster_capacity(5);
+        compressor.finalize_range(&range).unwrap();
+        compressor
+            .begin_ghost_session(session_id_from_stable_id(ghost_base))
+            .unwrap();
+        assert!(matches!(
+            compressor.try_generate_n_ids(3).unwrap_err(),
+            AllocatorError::ClusterCollision
+        ));
+        assert!(compressor.validate().is_valid());
+    }
+}
+
+#[test]
+fn test_ghost_session_layout() {
+    let mut compressor = IdCompressor::new();
+    let mut remote_compressor = IdCompressor::new();
+    let ghost_session_id = SessionId::new();
+    compressor.begin_ghost_session(ghost_session_id).unwrap();
+    _ = compressor.generate_n_ids(2);
+    generate_n_ids(&mut remote_compressor, 1);
+    let range = remote_compressor.take_next_range();
+    _ = compressor.finalize_range(&range);
+    _ = compressor.generate_n_ids(3);
+    // Ghost clusters are created with no spare capacity, and so a new cluster directly follows the previous one in
+    // session space once another session has claimed final space
+    let ghost_clusters: Vec<(i64, u64, u64, u64)> = compressor
+        .get_cluster_layout()
+        .iter()
+        .filter(|cluster| cluster.session_id == ghost_session_id)
+        .map(|cluster| {
+            (
+                SessionSpaceId::from(cluster.base_local_id).id(),
+                cluster.capacity,
+                cluster.count,
+                get_id_from_final_id(cluster.base_final_id),
+            )
+        })
+        .collect();
+    let remote_capacity = IdCompressor::get_default_cluster_capacity() + 1;
+    assert_eq!(
+        ghost_clusters,
+        vec![(-1, 2, 2, 0), (-3, 3, 3, 2 + remote_capacity)]
+    );
+}
+
+#[test]
+fn test_unused_ghost_session_is_not_persisted() {
+    let mut compressor = IdCompressor::new();
+    generate_n_ids(&mut compressor, 1);
+    let serialized = compressor.serialize(true);
+    compressor.begin_ghost_session(SessionId::new()).unwrap();
+    compressor.end_ghost_session();
+    assert_eq!(compressor.serialize(true), serialized);
+}
+
+#[test]
+fn test_ghosted_session_cannot_finalize() {
+    let mut compressor = IdCompressor::new();
+    let mut ghosted_compressor = IdCompressor::new();
+    compressor
+        .begin_ghost_session(ghosted_compressor.get_local_session_id())
+        .unwrap();
+    generate_n_ids(&mut compressor, 2);
+    compressor.end_ghost_session();
+    generate_n_ids(&mut ghosted_compressor, 2);
+    let range = ghosted_compressor.take_next_range();
+    assert!(matches!(
+        compressor.finalize_range(&range).unwrap_err(),
+        AllocatorError::RangeFinalizedOutOfOrder
+    ));
+    assert!(matches!(
+        compressor.begin_ghost_session(range.id).unwrap_err(),
+        AllocatorError::InvalidGhostSession
+    ));
+}
+
 #[test]
 fn test_id_range_creation() {
     const CLUSTER_CAPACITY: u64 = 5;
@@ -326,6 +708,103 @@ fn test_id_range_creation() {
     }
 }
 
+#[test]
+fn test_retract_ids() {
+    let mut compressor = IdCompressor::new();
+    _ = compressor.set_cluster_capacity(5);
+
+    // Retracted locals are reissued and excluded from the next range
+    let ids = generate_n_ids(&mut compressor, 3);
+    compressor.retract_ids(2).unwrap();
+    assert_eq!(compressor.generate_next_id(), ids[1]);
+    let range = compressor.take_next_range();
+    assert_eq!(range.range, Some((1, 2)));
+    assert!(matches!(
+        compressor.retract_ids(1).unwrap_err(),
+        AllocatorError::InvalidRetraction
+    ));
+    _ = compressor.finalize_range(&range);
+
+    // Eager finals cannot be retracted
+    let ids = generate_n_ids(&mut compressor, 7);
+    assert!(ids[4].is_final() && ids[5].is_local());
+    compressor.retract_ids(2).unwrap();
+    assert!(matches!(
+        compressor.retract_ids(1).unwrap_err(),
+        AllocatorError::InvalidRetraction
+    ));
+    assert!(compressor.normalize_to_op_space(ids[5]).is_err());
+    assert_eq!(compressor.take_next_range().range, Some((3, 5)));
+    compressor.retract_ids(0).unwrap();
+}
+
+#[test]
+fn test_pending_ranges() {
+    let mut compressor = IdCompressor::new();
+    let mut remote_compressor = IdCompressor::new();
+    assert!(compressor.get_pending_ranges().is_empty());
+    let mut ranges = Vec::new();
+    for count in [2, 3, 1] {
+        generate_n_ids(&mut compressor, count);
+        ranges.push(compressor.take_next_range());
+    }
+    _ = compressor.take_next_range();
+    let pending_ranges: Vec<Option<(u64, u64)>> = compressor
+        .get_pending_ranges()
+        .iter()
+        .map(|range| range.range)
+        .collect();
+    assert_eq!(
+        pending_ranges,
+        vec![Some((1, 2)), Some((3, 3)), Some((6, 1))]
+    );
+
+    // Pending ranges survive serialization
+    let roundtripped = IdCompressor::deserialize(&compressor.serialize(true)).unwrap();
+    assert!(roundtripped.equals_test_only(&compressor, true));
+
+    // Finalized ranges are no longer pending, and the remainder is resubmitted as a single range
+    _ = compressor.finalize_range(&ranges[0]);
+    _ = remote_compressor.finalize_range(&ranges[0]);
+    let resubmitted = compressor.resubmit_pending_ranges();
+    assert_eq!(resubmitted.len(), 1);
+    assert_eq!(resubmitted[0].range, Some((3, 4)));
+    assert_eq!(compressor.get_pending_ranges().len(), 2);
+    compressor.finalize_range(&resubmitted[0]).unwrap();
+    remote_compressor.finalize_range(&resubmitted[0]).unwrap();
+    assert!(compressor.get_pending_ranges().is_empty());
+    assert!(compressor.resubmit_pending_ranges().is_empty());
+}
+
+#[test]
+fn test_pending_ranges_keep_capacity_hints() {
+    let mut compressor = IdCompressor::new();
+    for capacity_hint in [Some(4), Some(4), None] {
+        compressor.set_capacity_hint(capacity_hint).unwrap();
+        generate_n_ids(&mut compressor, 2);
+        _ = compressor.take_next_range();
+    }
+    compressor.set_capacity_hint(Some(8)).unwrap();
+    let capacity_hints: Vec<Option<u64>> = compressor
+        .get_pending_ranges()
+        .iter()
+        .map(|range| range.capacity_hint)
+        .collect();
+    assert_eq!(capacity_hints, vec![Some(4), Some(4), None]);
+
+    // Only contiguous ranges taken under the same hint are merged, and hints survive serialization
+    let roundtripped = IdCompressor::deserialize(&compressor.serialize(true)).unwrap();
+    let resubmitted: Vec<_> = roundtripped
+        .resubmit_pending_ranges()
+        .iter()
+        .map(|range| (range.range, range.capacity_hint))
+        .collect();
+    assert_eq!(
+        resubmitted,
+        vec![(Some((1, 4)), Some(4)), (Some((5, 2)), None)]
+    );
+}
+
 #[test]
 fn test_prevent_finalizing_ranges_twice() {
     let mut compressor = IdCompressor::new();
@@ -351,12 +830,142 @@ fn test_prevent_finalizing_ranges_out_of_order() {
     ));
 }
 
+#[test]
+fn test_idempotent_finalization_ignores_replayed_ranges() {
+    let mut compressor = IdCompressor::new();
+    let mut remote_compressor = IdCompressor::new();
+    generate_n_ids(&mut remote_compressor, 3);
+    let range_a = remote_compressor.take_next_range();
+    generate_n_ids(&mut remote_compressor, 4);
+    let range_b = remote_compressor.take_next_range();
+
+    assert!(!compressor
+        .finalize_range_idempotent(&range_a)
+        .unwrap()
+        .spans
+        .is_empty());
+    let replayed = compressor.finalize_range_idempotent(&range_a).unwrap();
+    assert!(replayed.spans.is_empty() && replayed.cluster_events.is_empty());
+    _ = compressor.finalize_range_idempotent(&range_b).unwrap();
+    for range in [&range_a, &range_b] {
+        assert!(compressor
+            .finalize_range_idempotent(range)
+            .unwrap()
+            .spans
+            .is_empty());
+    }
+
+    // Sub-ranges and merges of finalized ranges were never finalized, and so are not replays
+    for range in [(2, 1), (1, 2), (4, 3), (1, 7)] {
+        let finalized_ids = IdRange {
+            id: range_a.id,
+            range: Some(range),
+            capacity_hint: None,
+        };
+        assert!(matches!(
+            compressor
+                .finalize_range_idempotent(&finalized_ids)
+                .unwrap_err(),
+            AllocatorError::RangeFinalizedOutOfOrder
+        ));
+    }
+
+    // Malformed ranges are rejected before being classified
+    for malformed in [(0, 1), (1, 0), (2, u64::MAX)] {
+        let malformed_range = IdRange {
+            id: range_a.id,
+            range: Some(malformed),
+            capacity_hint: None,
+        };
+        assert!(matches!(
+            compressor
+                .finalize_range_idempotent(&malformed_range)
+                .unwrap_err(),
+            AllocatorError::MalformedIdRange
+        ));
+    }
+
+    // Ranges that straddle the finalized boundary are rejected
+    generate_n_ids(&mut remote_compressor, 3);
+    let range_c = remote_compressor.take_next_range();
+    let straddling_range = IdRange {
+        id: range_c.id,
+        range: Some((6, 4)),
+        capacity_hint: None,
+    };
+    assert!(matches!(
+        compressor
+            .finalize_range_idempotent(&straddling_range)
+            .unwrap_err(),
+        AllocatorError::RangePartiallyFinalized
+    ));
+
+    // Genuinely out of order ranges are still reported
+    generate_n_ids(&mut remote_compressor, 2);
+    let range_d = remote_compressor.take_next_range();
+    assert!(matches!(
+        compressor.finalize_range_idempotent(&range_d).unwrap_err(),
+        AllocatorError::RangeFinalizedOutOfOrder
+    ));
+    _ = compressor.finalize_range_idempotent(&range_c).unwrap();
+    _ = compressor.finalize_range_idempotent(&range_d).unwrap();
+}
+
+#[test]
+fn test_batch_finalization_is_atomic() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_b = IdCompressor::new();
+    let mut compressor_c = IdCompressor::new();
+    _ = compressor.set_cluster_capacity(3);
+    generate_n_ids(&mut compressor, 2);
+    finalize_next_range(&mut compressor);
+    _ = compressor.get_telemetry_stats();
+    let serialized = compressor.serialize(false);
+
+    generate_n_ids(&mut compressor_b, 4);
+    let range_b = compressor_b.take_next_range();
+    generate_n_ids(&mut compressor, 6);
+    let range_a = compressor.take_next_range();
+    generate_n_ids(&mut compressor_c, 2);
+    let _skipped_range_c = compressor_c.take_next_range();
+    generate_n_ids(&mut compressor_c, 2);
+    let range_c = compressor_c.take_next_range();
+
+    let copy = |range: &IdRange| IdRange {
+        id: range.id,
+        range: range.range,
+        capacity_hint: range.capacity_hint,
+    };
+    let error = compressor
+        .finalize_ranges(&[copy(&range_b), copy(&range_a), range_c])
+        .unwrap_err();
+    assert_eq!(error.index, 2);
+    assert!(matches!(
+        error.error,
+        AllocatorError::RangeFinalizedOutOfOrder
+    ));
+    assert_eq!(compressor.serialize(false), serialized);
+    let telemetry = compressor.get_telemetry_stats();
+    assert_eq!(telemetry.cluster_creation_count, 0);
+    assert_eq!(telemetry.expansion_count, 0);
+
+    let mut expected = IdCompressor::deserialize(&serialized).unwrap();
+    _ = expected.finalize_range(&range_b);
+    _ = expected.finalize_range(&range_a);
+    let outcomes = compressor.finalize_ranges(&[range_b, range_a]).unwrap();
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[1].spans.len(), 2);
+    assert_eq!(compressor.serialize(false), expected.serialize(false));
+    assert_eq!(compressor.get_telemetry_stats().cluster_creation_count, 2);
+}
+
 #[test]
 fn test_prevents_finalizing_malformed_ranges() {
     let mut compressor = IdCompressor::new();
     let bad_range = IdRange {
         id: compressor.get_local_session_id(),
         range: Some((2, 0)),
+        capacity_hint: None,
     };
     assert!(matches!(
         compressor.finalize_range(&bad_range).unwrap_err(),
@@ -442,6 +1051,255 @@ fn test_overflows_to_new_cluster() {
     assert_eq!(telemetry.cluster_creation_count, 1);
 }
 
+#[test]
+fn test_finalization_outcome() {
+    let mut compressor_a = IdCompressor::new();
+    let mut compressor_b = IdCompressor::new();
+    _ = compressor_a.set_cluster_capacity(3);
+    let span = |base_local: i64, base_final: u64, count: u64| FinalizedSpan {
+        base_local: local_id::local_id_from_id(base_local),
+        base_final: final_id::final_id_from_id(base_final),
+        count,
+    };
+
+    // Empty ranges have no effect
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        compressor_a.finalize_range_with_outcome(&range).unwrap(),
+        FinalizationOutcome {
+            spans: vec![],
+            cluster_events: vec![],
+        }
+    );
+
+    // Create the initial local cluster
+    generate_n_ids(&mut compressor_a, 2);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        compressor_a.finalize_range_with_outcome(&range).unwrap(),
+        FinalizationOutcome {
+            spans: vec![span(-1, 0, 2)],
+            cluster_events: vec![ClusterEvent::Created(final_id::final_id_from_id(0), 5)],
+        }
+    );
+
+    // Create a foreign cluster
+    generate_n_ids(&mut compressor_b, 2);
+    let range = compressor_b.take_next_range();
+    assert_eq!(
+        compressor_a.finalize_range_with_outcome(&range).unwrap(),
+        FinalizationOutcome {
+            spans: vec![span(-1, 5, 2)],
+            cluster_events: vec![ClusterEvent::Created(final_id::final_id_from_id(5), 5)],
+        }
+    );
+
+    // Fill the initial local cluster and overflow to a new cluster
+    generate_n_ids(&mut compressor_a, 8);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        compressor_a.finalize_range_with_outcome(&range).unwrap(),
+        FinalizationOutcome {
+            spans: vec![span(-3, 2, 3), span(-6, 10, 5)],
+            cluster_events: vec![ClusterEvent::Created(final_id::final_id_from_id(10), 8)],
+        }
+    );
+
+    // Fit within the new cluster
+    generate_n_ids(&mut compressor_a, 2);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        compressor_a.finalize_range_with_outcome(&range).unwrap(),
+        FinalizationOutcome {
+            spans: vec![span(-11, 15, 2)],
+            cluster_events: vec![],
+        }
+    );
+
+    // Expand the new cluster
+    generate_n_ids(&mut compressor_a, 5);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        compressor_a.finalize_range_with_outcome(&range).unwrap(),
+        FinalizationOutcome {
+            spans: vec![span(-13, 17, 5)],
+            cluster_events: vec![ClusterEvent::Expanded(final_id::final_id_from_id(10), 7)],
+        }
+    );
+
+    // Telemetry is derived from the outcomes, and local counts exclude the foreign cluster
+    let telemetry = compressor_a.get_telemetry_stats();
+    assert_eq!(telemetry.cluster_creation_count, 3);
+    assert_eq!(telemetry.expansion_count, 1);
+    assert_eq!(telemetry.local_cluster_creation_count, 2);
+    assert_eq!(telemetry.local_expansion_count, 1);
+
+    // Batched finalizations are attributed to the session of each range
+    generate_n_ids(&mut compressor_a, 20);
+    generate_n_ids(&mut compressor_b, 20);
+    let ranges = [
+        compressor_b.take_next_range(),
+        compressor_a.take_next_range(),
+    ];
+    _ = compressor_a.finalize_ranges(&ranges).unwrap();
+    let telemetry = compressor_a.get_telemetry_stats();
+    assert_eq!(telemetry.cluster_creation_count, 2);
+    assert_eq!(telemetry.local_cluster_creation_count, 1);
+    assert_eq!(telemetry.local_expansion_count, 0);
+}
+
+#[test]
+fn test_check_range_does_not_mutate() {
+    let mut compressor_a = IdCompressor::new();
+    let mut compressor_b = IdCompressor::new();
+    _ = compressor_a.set_cluster_capacity(3);
+    let check_and_finalize = |compressor: &mut IdCompressor, range: &IdRange| {
+        let serialized = compressor.serialize(false);
+        let plan = compressor.check_range(range).unwrap();
+        assert_eq!(compressor.serialize(false), serialized);
+        let outcome = compressor.finalize_range_with_outcome(range).unwrap();
+        assert_eq!(plan.spans, outcome.spans);
+        plan.placement
+    };
+
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        check_and_finalize(&mut compressor_a, &range),
+        RangePlacement::Empty
+    );
+    generate_n_ids(&mut compressor_a, 2);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        check_and_finalize(&mut compressor_a, &range),
+        RangePlacement::CreatesFirstCluster(final_id::final_id_from_id(0), 5)
+    );
+    generate_n_ids(&mut compressor_b, 2);
+    let range = compressor_b.take_next_range();
+    assert_eq!(
+        check_and_finalize(&mut compressor_a, &range),
+        RangePlacement::CreatesFirstCluster(final_id::final_id_from_id(5), 5)
+    );
+    generate_n_ids(&mut compressor_a, 8);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        check_and_finalize(&mut compressor_a, &range),
+        RangePlacement::OverflowsToNewCluster(final_id::final_id_from_id(10), 8)
+    );
+    generate_n_ids(&mut compressor_a, 2);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        check_and_finalize(&mut compressor_a, &range),
+        RangePlacement::FitsTailCluster
+    );
+    generate_n_ids(&mut compressor_a, 5);
+    let range = compressor_a.take_next_range();
+    assert_eq!(
+        check_and_finalize(&mut compressor_a, &range),
+        RangePlacement::ExpandsTailCluster(final_id::final_id_from_id(10), 7)
+    );
+
+    // Ranges that would fail to finalize fail to check
+    assert!(matches!(
+        compressor_a.check_range(&range).unwrap_err(),
+        AllocatorError::RangeFinalizedOutOfOrder
+    ));
+    let malformed_range = IdRange {
+        id: compressor_b.get_local_session_id(),
+        range: Some((3, 0)),
+        capacity_hint: None,
+    };
+    assert!(matches!(
+        compressor_a.check_range(&malformed_range).unwrap_err(),
+        AllocatorError::MalformedIdRange
+    ));
+}
+
+#[test]
+fn test_cluster_layout() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_2 = IdCompressor::new();
+    _ = compressor.set_cluster_capacity(3);
+    assert!(compressor.get_cluster_layout().is_empty());
+    let session_id = compressor.get_local_session_id();
+    let session_id_2 = compressor_2.get_local_session_id();
+
+    generate_n_ids(&mut compressor, 2);
+    finalize_next_range(&mut compressor);
+    generate_n_ids(&mut compressor_2, 1);
+    let range = compressor_2.take_next_range();
+    _ = compressor.finalize_range(&range);
+    generate_n_ids(&mut compressor, 5);
+    finalize_next_range(&mut compressor);
+
+    let snapshot = |session_id, base_final, base_local, capacity, count| ClusterSnapshot {
+        session_id,
+        base_final_id: final_id::final_id_from_id(base_final),
+        base_local_id: local_id::local_id_from_id(base_local),
+        capacity,
+        count,
+    };
+    assert_eq!(
+        compressor.get_cluster_layout(),
+        vec![
+            snapshot(session_id, 0, -1, 5, 5),
+            snapshot(session_id_2, 5, -1, 4, 1),
+            snapshot(session_id, 9, -6, 5, 2),
+        ]
+    );
+}
+
+#[test]
+fn test_space_report() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_2 = IdCompressor::new();
+    _ = compressor.set_cluster_capacity(3);
+    let empty_report = compressor.space_report();
+    assert_eq!(empty_report.cluster_count, 0);
+    assert!(empty_report.sessions.is_empty());
+
+    generate_n_ids(&mut compressor, 2);
+    finalize_next_range(&mut compressor);
+    generate_n_ids(&mut compressor_2, 1);
+    let range = compressor_2.take_next_range();
+    _ = compressor.finalize_range(&range);
+    generate_n_ids(&mut compressor, 5);
+    finalize_next_range(&mut compressor);
+
+    let report = compressor.space_report();
+    assert_eq!(report.cluster_count, 3);
+    assert_eq!(report.capacity, 14);
+    assert_eq!(report.count, 8);
+    assert_eq!(report.unused_count, 6);
+    assert_eq!(report.sessions.len(), 2);
+    for session_report in report.sessions {
+        if session_report.session_id == compressor.get_local_session_id() {
+            assert_eq!(
+                (
+                    session_report.cluster_count,
+                    session_report.capacity,
+                    session_report.count,
+                    session_report.unused_count
+                ),
+                (2, 10, 7, 3)
+            );
+        } else {
+            assert_eq!(
+                session_report.session_id,
+                compressor_2.get_local_session_id()
+            );
+            assert_eq!(
+                (
+                    session_report.cluster_count,
+                    session_report.capacity,
+                    session_report.count,
+                    session_report.unused_count
+                ),
+                (1, 4, 1, 3)
+            );
+        }
+    }
+}
+
 #[test]
 fn test_recompress_own_stable_id() {
     let mut compressor = IdCompressor::new();
@@ -520,6 +1378,170 @@ fn test_recompress_unfinalized_eager_final() {
     assert_eq!(recompress_result.ok().unwrap(), id_2);
 }
 
+#[test]
+fn test_get_status() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_2 = IdCompressor::new();
+    _ = compressor.set_cluster_capacity(3);
+    let session_id = compressor.get_local_session_id();
+    let session_id_2 = compressor_2.get_local_session_id();
+
+    let local = compressor.generate_next_id();
+    assert_eq!(
+        compressor.get_status(local),
+        IdStatus::UnfinalizedLocal(session_id)
+    );
+    finalize_next_range(&mut compressor);
+    let final_id = match compressor.normalize_to_op_space(local).unwrap().to_space() {
+        CompressedId::Final(final_id) => final_id,
+        CompressedId::Local(_) => panic!("ID is not finalized."),
+    };
+    assert_eq!(
+        compressor.get_status(local),
+        IdStatus::FinalizedLocal(session_id, final_id)
+    );
+
+    let eager_final = compressor.generate_next_id();
+    assert_eq!(
+        compressor.get_status(eager_final),
+        IdStatus::EagerFinal(session_id)
+    );
+    finalize_next_range(&mut compressor);
+    assert_eq!(
+        compressor.get_status(eager_final),
+        IdStatus::Final(session_id)
+    );
+
+    let remote_local = compressor_2.generate_next_id();
+    let range = compressor_2.take_next_range();
+    _ = compressor.finalize_range(&range);
+    let remote_final = compressor
+        .normalize_to_session_space(
+            compressor_2.normalize_to_op_space(remote_local).unwrap(),
+            session_id_2,
+        )
+        .unwrap();
+    assert_eq!(
+        compressor.get_status(remote_final),
+        IdStatus::Final(session_id_2)
+    );
+
+    // IDs that fail to decompress are unknown
+    for id in [
+        SessionSpaceId::from_id(-100),
+        SessionSpaceId::from_id(2),
+        SessionSpaceId::from_id(1_000),
+    ] {
+        assert!(compressor.decompress(id).is_err());
+        assert_eq!(compressor.get_status(id), IdStatus::Unknown);
+    }
+}
+
+#[test]
+fn test_get_originating_session() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_2 = IdCompressor::new();
+    let session_id = compressor.get_local_session_id();
+    let session_id_2 = compressor_2.get_local_session_id();
+
+    let local = compressor.generate_next_id();
+    finalize_next_range(&mut compressor);
+    let eager_final = compressor.generate_next_id();
+    let remote_local = compressor_2.generate_next_id();
+    let range = compressor_2.take_next_range();
+    _ = compressor.finalize_range(&range);
+    let remote_final = compressor
+        .normalize_to_session_space(
+            compressor_2.normalize_to_op_space(remote_local).unwrap(),
+            session_id_2,
+        )
+        .unwrap();
+
+    for (id, expected_session) in [
+        (local, session_id),
+        (eager_final, session_id),
+        (remote_final, session_id_2),
+    ] {
+        assert_eq!(
+            compressor.get_originating_session(id).unwrap(),
+            expected_session
+        );
+        assert_eq!(
+            compressor.get_originating_session_token(id).unwrap(),
+            compressor
+                .get_session_token_from_session_id(expected_session)
+                .unwrap()
+        );
+    }
+    for id in [
+        SessionSpaceId::from_id(-100),
+        SessionSpaceId::from_id(1_000_000),
+    ] {
+        assert!(matches!(
+            compressor.get_originating_session(id).unwrap_err(),
+            AllocatorError::InvalidSessionSpaceId
+        ));
+        assert!(matches!(
+            compressor.get_originating_session_token(id).unwrap_err(),
+            AllocatorError::InvalidSessionSpaceId
+        ));
+    }
+}
+
+#[test]
+fn test_finalized_id_iterators() {
+    let mut compressor = IdCompressor::new();
+    let mut compressor_2 = IdCompressor::new();
+    _ = compressor.set_cluster_capacity(2);
+    _ = compressor_2.set_cluster_capacity(2);
+    let session_id_2 = compressor_2.get_local_session_id();
+    let mut local_ids = Vec::new();
+    for round in 1..5 {
+        local_ids.extend(generate_n_ids(&mut compressor, round));
+        let range = compressor.take_next_range();
+        _ = compressor.finalize_range(&range);
+        _ = compressor_2.finalize_range(&range);
+        generate_n_ids(&mut compressor_2, round);
+        let range = compressor_2.take_next_range();
+        _ = compressor.finalize_range(&range);
+        _ = compressor_2.finalize_range(&range);
+    }
+    // Unfinalized IDs are skipped
+    let unfinalized_id = compressor.generate_next_id();
+
+    let session_ids: Vec<(SessionSpaceId, StableId)> = compressor
+        .iter_session_ids(compressor.get_local_session_id())
+        .unwrap()
+        .collect();
+    let expected: Vec<(SessionSpaceId, StableId)> = local_ids
+        .iter()
+        .map(|&id| (id, compressor.decompress(id).unwrap()))
+        .collect();
+    assert_eq!(session_ids, expected);
+    assert!(session_ids.iter().all(|&(id, _)| id != unfinalized_id));
+
+    let all_ids: Vec<(SessionSpaceId, StableId)> = compressor.iter_finalized_ids().collect();
+    assert_eq!(all_ids.len(), 20);
+    let mut previous_final = None;
+    for &(id, stable_id) in &all_ids {
+        assert_eq!(compressor.decompress(id).unwrap(), stable_id);
+        assert_eq!(compressor.recompress(stable_id).unwrap(), id);
+        let final_id = compressor.normalize_to_op_space(id).unwrap();
+        assert!(final_id.is_final());
+        assert!(previous_final < Some(final_id));
+        previous_final = Some(final_id);
+    }
+    let remote_ids = compressor.iter_session_ids(session_id_2).unwrap();
+    assert_eq!(remote_ids.count(), 10);
+    assert!(matches!(
+        compressor
+            .iter_session_ids(IdCompressor::new().get_local_session_id())
+            .err()
+            .unwrap(),
+        AllocatorError::NoTokenForSession
+    ));
+}
+
 #[test]
 fn test_decompress_unknown_id() {
     let compressor = IdCompressor::new();
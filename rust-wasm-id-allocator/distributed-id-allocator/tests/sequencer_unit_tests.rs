@@ -0,0 +1,259 @@
+use distributed_id_allocator::compressor::*;
+use id_types::*;
+
+fn to_final(compressor: &IdCompressor, id: SessionSpaceId) -> FinalId {
+    match compressor.normalize_to_op_space(id).unwrap().to_space() {
+        CompressedId::Final(final_id) => final_id,
+        CompressedId::Local(_) => panic!("ID is not finalized."),
+    }
+}
+
+// Generates IDs in several clients and finalizes every range in all clients and in a sequencer.
+// Returns the clients and every generated ID (paired with the index of its client).
+fn sequence_clients(
+    sequencer: &mut SequencerCompressor,
+    client_count: usize,
+) -> (Vec<IdCompressor>, Vec<(usize, SessionSpaceId)>) {
+    let mut clients: Vec<IdCompressor> = (0..client_count).map(|_| IdCompressor::new()).collect();
+    for client in clients.iter_mut() {
+        client.set_cluster_capacity(5).unwrap();
+    }
+    sequencer.set_cluster_capacity(5).unwrap();
+    let mut ids = Vec::new();
+    for round in 0..10 {
+        for client_index in 0..client_count {
+            for _ in 0..(round % 4 + 1) {
+                ids.push((client_index, clients[client_index].generate_next_id()));
+            }
+            let range = clients[client_index].take_next_range();
+            for client in clients.iter_mut() {
+                client.finalize_range(&range).unwrap();
+            }
+            sequencer.finalize_range(&range).unwrap();
+        }
+    }
+    (clients, ids)
+}
+
+#[test]
+fn test_sequencer_matches_clients() {
+    let mut sequencer = SequencerCompressor::new();
+    let (clients, ids) = sequence_clients(&mut sequencer, 3);
+    for (client_index, id) in ids {
+        let client = &clients[client_index];
+        let final_id = to_final(client, id);
+        let stable_id = client.decompress(id).unwrap();
+        assert_eq!(sequencer.decompress(final_id).unwrap(), stable_id);
+        assert_eq!(sequencer.recompress(stable_id).unwrap(), final_id);
+    }
+    for client in &clients {
+        assert_eq!(sequencer.get_cluster_layout(), client.get_cluster_layout());
+    }
+}
+
+#[test]
+fn test_sequencer_rejects_unfinalized_ids() {
+    let mut sequencer = SequencerCompressor::new();
+    let mut client = IdCompressor::new();
+    _ = client.generate_next_id();
+    let range = client.take_next_range();
+    client.finalize_range(&range).unwrap();
+    sequencer.finalize_range(&range).unwrap();
+
+    // An eager final has been allocated, but not finalized
+    let eager_final = client.generate_next_id();
+    assert!(eager_final.is_final());
+    let final_id = to_final(&client, eager_final);
+    assert!(matches!(
+        sequencer.decompress(final_id).unwrap_err(),
+        AllocatorError::InvalidFinalId
+    ));
+    let stable_id = client.decompress(eager_final).unwrap();
+    assert!(sequencer.recompress(stable_id).is_err());
+
+    let range = client.take_next_range();
+    sequencer.finalize_range(&range).unwrap();
+    assert_eq!(sequencer.decompress(final_id).unwrap(), stable_id);
+    assert_eq!(sequencer.recompress(stable_id).unwrap(), final_id);
+}
+
+#[test]
+fn test_sequencer_rejects_out_of_order_ranges() {
+    let mut sequencer = SequencerCompressor::new();
+    let mut client = IdCompressor::new();
+    _ = client.generate_next_id();
+    let _skipped_range = client.take_next_range();
+    _ = client.generate_next_id();
+    let range = client.take_next_range();
+    assert!(matches!(
+        sequencer.finalize_range(&range).unwrap_err(),
+        AllocatorError::RangeFinalizedOutOfOrder
+    ));
+}
+
+#[test]
+fn test_sequencer_serialization_is_client_compatible() {
+    let mut sequencer = SequencerCompressor::new();
+    let (clients, _) = sequence_clients(&mut sequencer, 3);
+    let client = &clients[0];
+
+    let serialized = sequencer.serialize();
+    let from_sequencer = IdCompressor::deserialize(&serialized).unwrap();
+    assert!(from_sequencer.equals_test_only(client, false));
+
+    for with_local in [false, true] {
+        let from_client = SequencerCompressor::deserialize(&client.serialize(with_local)).unwrap();
+        let roundtripped = IdCompressor::deserialize(&from_client.serialize()).unwrap();
+        assert!(roundtripped.equals_test_only(client, false));
+    }
+
+    let roundtripped = SequencerCompressor::deserialize(&serialized).unwrap();
+    assert_eq!(roundtripped.serialize(), serialized);
+}
+
+#[test]
+fn test_validate_op_space_ids() {
+    let mut sequencer = SequencerCompressor::new();
+    let (mut clients, ids) = sequence_clients(&mut sequencer, 2);
+    let session_a = clients[0].get_local_session_id();
+    let session_b = clients[1].get_local_session_id();
+
+    for (client_index, id) in ids {
+        let client = &clients[client_index];
+        let session_id = client.get_local_session_id();
+        let final_id = to_final(client, id);
+        if id.is_local() {
+            let op_space_id = OpSpaceId::from_id(id.id());
+            assert_eq!(
+                sequencer
+                    .validate_op_space_id(op_space_id, session_id)
+                    .unwrap(),
+                IdStatus::FinalizedLocal(session_id, final_id)
+            );
+        }
+        // Finals are valid regardless of originator
+        for originator in [session_a, session_b] {
+            assert_eq!(
+                sequencer
+                    .validate_op_space_id(OpSpaceId::from(final_id), originator)
+                    .unwrap(),
+                IdStatus::Final(session_id)
+            );
+        }
+    }
+
+    // Locals that have not been finalized
+    assert!(matches!(
+        sequencer
+            .validate_op_space_id(OpSpaceId::from_id(-1000), session_b)
+            .unwrap_err(),
+        AllocatorError::InvalidOpSpaceId
+    ));
+
+    // Locals from unknown sessions
+    let unknown_client = IdCompressor::new();
+    assert!(matches!(
+        sequencer
+            .validate_op_space_id(
+                OpSpaceId::from_id(-1),
+                unknown_client.get_local_session_id()
+            )
+            .unwrap_err(),
+        AllocatorError::NoTokenForSession
+    ));
+
+    // Allocated but unfinalized eager finals, and finals beyond the final ID limit
+    let eager_final = clients[1].generate_next_id();
+    assert!(eager_final.is_final());
+    let eager_final = to_final(&clients[1], eager_final);
+    assert!(matches!(
+        sequencer
+            .validate_op_space_id(OpSpaceId::from(eager_final), session_b)
+            .unwrap_err(),
+        AllocatorError::InvalidOpSpaceId
+    ));
+    assert!(matches!(
+        sequencer
+            .validate_op_space_id(OpSpaceId::from_id(1_000_000), session_b)
+            .unwrap_err(),
+        AllocatorError::InvalidOpSpaceId
+    ));
+}
+
+#[test]
+fn test_finalize_op_space_ids() {
+    let mut sequencer = SequencerCompressor::new();
+    let (clients, ids) = sequence_clients(&mut sequencer, 2);
+    let session_b = clients[1].get_local_session_id();
+
+    for (client_index, client) in clients.iter().enumerate() {
+        let op_space_ids: Vec<OpSpaceId> = ids
+            .iter()
+            .filter(|(index, _)| *index == client_index)
+            .map(|&(_, id)| client.normalize_to_op_space(id).unwrap())
+            .collect();
+        let locals: Vec<OpSpaceId> = ids
+            .iter()
+            .filter(|(index, id)| *index == client_index && id.is_local())
+            .map(|&(_, id)| OpSpaceId::from_id(id.id()))
+            .collect();
+        assert!(!locals.is_empty());
+        let expected: Vec<OpSpaceId> = ids
+            .iter()
+            .filter(|(index, id)| *index == client_index && id.is_local())
+            .map(|&(_, id)| OpSpaceId::from(to_final(client, id)))
+            .collect();
+        let session_id = client.get_local_session_id();
+        assert_eq!(
+            sequencer
+                .finalize_op_space_ids(session_id, &locals)
+                .unwrap(),
+            expected
+        );
+        let rewritten = sequencer
+            .finalize_op_space_ids(session_id, &op_space_ids)
+            .unwrap();
+        assert!(rewritten.iter().all(|id| id.is_final()));
+        for (&(_, id), rewritten_id) in ids
+            .iter()
+            .filter(|(index, _)| *index == client_index)
+            .zip(rewritten)
+        {
+            assert_eq!(
+                clients[1 - client_index]
+                    .normalize_to_session_space(rewritten_id, session_id)
+                    .unwrap(),
+                clients[1 - client_index]
+                    .normalize_to_session_space(
+                        client.normalize_to_op_space(id).unwrap(),
+                        session_id
+                    )
+                    .unwrap()
+            );
+        }
+    }
+
+    assert_eq!(
+        sequencer.finalize_op_space_ids(session_b, &[]).unwrap(),
+        vec![]
+    );
+    assert!(matches!(
+        sequencer
+            .finalize_op_space_ids(
+                session_b,
+                &[OpSpaceId::from_id(-1), OpSpaceId::from_id(-1000)]
+            )
+            .unwrap_err(),
+        AllocatorError::InvalidOpSpaceId
+    ));
+    let unknown_client = IdCompressor::new();
+    assert!(matches!(
+        sequencer
+            .finalize_op_space_ids(
+                unknown_client.get_local_session_id(),
+                &[OpSpaceId::from_id(-1)]
+            )
+            .unwrap_err(),
+        AllocatorError::NoTokenForSession
+    ));
+}
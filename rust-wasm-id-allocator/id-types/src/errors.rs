@@ -30,6 +30,30 @@ pub enum AllocatorError {
 
     /// Attempted to normalize an ID from an unknown session.
     NoTokenForSession,
+
+    /// Ghost session already in progress, or ghost session is the local session.
+    InvalidGhostSession,
+
+    /// Failed to decompress FinalId.
+    InvalidFinalId,
+
+    /// ID Range overlaps both finalized and unfinalized IDs.
+    RangePartiallyFinalized,
+
+    /// Retracted IDs must be the most recently generated locals not yet taken in a range.
+    InvalidRetraction,
+
+    /// Capacity change was proposed against a cluster capacity other than the current one.
+    CapacityChangeDiverged,
+
+    /// Allocating the ID would exceed the final ID ceiling.
+    FinalIdSpaceExhausted,
+
+    /// Integer is not a valid value for the ID type.
+    IdOutOfRange,
+
+    /// Stable ID arithmetic exceeded the bounds of the UUID space.
+    StableIdOverflow,
 }
 
 /// Defines a way to get an error string.
@@ -54,6 +78,20 @@ impl ErrorString for AllocatorError {
             AllocatorError::NoTokenForSession => {
                 "No IDs have ever been finalized by the supplied session."
             }
+            AllocatorError::InvalidGhostSession => {
+                "Cannot begin a ghost session while one is in progress or for the local session."
+            }
+            AllocatorError::InvalidFinalId => "Unknown final ID.",
+            AllocatorError::RangePartiallyFinalized => "Range is partially finalized.",
+            AllocatorError::InvalidRetraction => {
+                "Only the most recently generated local IDs that have not been taken in a range can be retracted."
+            }
+            AllocatorError::CapacityChangeDiverged => {
+                "Capacity change does not apply to the current cluster capacity."
+            }
+            AllocatorError::FinalIdSpaceExhausted => "Final ID space exhausted.",
+            AllocatorError::IdOutOfRange => "Value is out of range for the ID type.",
+            AllocatorError::StableIdOverflow => "Stable ID is out of range of the UUID space.",
         }
     }
 }
@@ -1,3 +1,5 @@
+use crate::AllocatorError;
+
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug)]
 /// A compressed ID that is stable and unique within the scope of network of compressors (i.e. a document).
 /// It can only be used/decompressed in the context of the originating document.
@@ -15,6 +17,16 @@ pub const fn get_id_from_final_id(final_id: FinalId) -> u64 {
     final_id.id
 }
 
+impl TryFrom<i64> for FinalId {
+    type Error = AllocatorError;
+    /// Creates a final ID from an i64, which must be non-negative.
+    fn try_from(id: i64) -> Result<Self, Self::Error> {
+        u64::try_from(id)
+            .map(final_id_from_id)
+            .map_err(|_| AllocatorError::IdOutOfRange)
+    }
+}
+
 impl std::ops::Add<u64> for FinalId {
     type Output = FinalId;
     fn add(self, rhs: u64) -> Self::Output {
@@ -29,3 +41,20 @@ impl std::ops::Sub<FinalId> for FinalId {
         self.id as i64 - rhs.id as i64
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(FinalId::try_from(0).unwrap(), final_id_from_id(0));
+        assert_eq!(FinalId::try_from(3).unwrap(), final_id_from_id(3));
+        for id in [-1, i64::MIN] {
+            assert!(matches!(
+                FinalId::try_from(id),
+                Err(AllocatorError::IdOutOfRange)
+            ));
+        }
+    }
+}
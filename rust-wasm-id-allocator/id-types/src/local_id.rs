@@ -1,3 +1,4 @@
+use crate::AllocatorError;
 use std::ops::Sub;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -35,6 +36,18 @@ pub const fn get_id_from_local_id(local_id: LocalId) -> i64 {
     local_id.id
 }
 
+impl TryFrom<i64> for LocalId {
+    type Error = AllocatorError;
+    /// Creates a local ID from an i64, which must be negative and have a representable generation count.
+    fn try_from(id: i64) -> Result<Self, Self::Error> {
+        if id < 0 && id != i64::MIN {
+            Ok(LocalId { id })
+        } else {
+            Err(AllocatorError::IdOutOfRange)
+        }
+    }
+}
+
 impl PartialEq<i64> for LocalId {
     fn eq(&self, other: &i64) -> bool {
         self.id == *other
@@ -47,3 +60,19 @@ impl Sub<u64> for LocalId {
         local_id_from_id(self.id - rhs as i64)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(LocalId::try_from(-1).unwrap(), -1);
+        for id in [0, 1, i64::MIN] {
+            assert!(matches!(
+                LocalId::try_from(id),
+                Err(AllocatorError::IdOutOfRange)
+            ));
+        }
+    }
+}
@@ -59,3 +59,31 @@ impl From<FinalId> for SessionSpaceId {
         }
     }
 }
+
+impl TryFrom<i64> for SessionSpaceId {
+    type Error = AllocatorError;
+    /// Creates a SessionSpaceId from an i64, which must be a valid local or final ID.
+    fn try_from(id: i64) -> Result<Self, Self::Error> {
+        if id == i64::MIN {
+            Err(AllocatorError::IdOutOfRange)
+        } else {
+            Ok(SessionSpaceId { id })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from() {
+        for id in [-1, 0, 3] {
+            assert_eq!(SessionSpaceId::try_from(id).unwrap().id(), id);
+        }
+        assert!(matches!(
+            SessionSpaceId::try_from(i64::MIN),
+            Err(AllocatorError::IdOutOfRange)
+        ));
+    }
+}
@@ -13,6 +13,21 @@ impl StableId {
     pub fn nil() -> StableId {
         StableId { id: 0 }
     }
+
+    /// Returns the StableId `offset` greater than this one, or None if it would exceed the UUID space.
+    pub fn checked_add(self, offset: u64) -> Option<StableId> {
+        self.id
+            .checked_add(offset as u128)
+            .filter(|&id| id <= MAX_STRIPPED_ID)
+            .map(|id| StableId { id })
+    }
+
+    /// Returns the StableId `offset` less than this one, or None if it would precede the nil UUID.
+    pub fn checked_sub(self, offset: u64) -> Option<StableId> {
+        self.id
+            .checked_sub(offset as u128)
+            .map(|id| StableId { id })
+    }
 }
 
 // xxxxxxxx-xxxx-Mxxx-Nxxx-xxxxxxxxxxxx
@@ -27,6 +42,8 @@ const STRIPPED_MIDDIE_BITTIES_MASK: u128 = MIDDIE_BITTIES_MASK >> 2;
 // Note: leading character should be 3 to mask at 0011
 // The more-significant half of the N nibble is used to denote the variant (10xx)
 const LOWER_MASK: u128 = 0x3FFFFFFFFFFFFFFF;
+// The largest ID representable once version/variant bits are removed (122 bits)
+const MAX_STRIPPED_ID: u128 = (1 << 122) - 1;
 
 impl From<StableId> for Vec<u8> {
     fn from(id: StableId) -> Self {
@@ -137,6 +154,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_checked_arithmetic() {
+        let stable_id = StableId::from(0x00000000000040008000000000000005);
+        assert_eq!(stable_id.checked_add(5), Some(stable_id + 5));
+        assert_eq!(stable_id.checked_sub(5), Some(StableId::nil()));
+        assert_eq!(stable_id.checked_sub(6), None);
+        let max_stable_id = StableId::from(u128::MAX);
+        assert_eq!(
+            u128::from(max_stable_id),
+            0xFFFFFFFFFFFF4FFFBFFFFFFFFFFFFFFF
+        );
+        assert_eq!(max_stable_id.checked_add(0), Some(max_stable_id));
+        assert_eq!(max_stable_id.checked_add(1), None);
+        assert_eq!((max_stable_id - 10).checked_add(10), Some(max_stable_id));
+    }
+
     #[test]
     fn test_u64_subtraction() {
         let uuid_1 = StableId::from(0x100000000000400080000000000000F0);
@@ -50,6 +50,8 @@ pub struct IdCompressor {
 }
 
 const MAX_DEFAULT_CLUSTER_CAPACITY: f64 = 2_i32.pow(11) as f64;
+// IDs are passed across the interop boundary as f64s, which represent integers exactly only below 2^53.
+const FINAL_ID_CEILING: u64 = 2_u64.pow(53);
 
 #[wasm_bindgen]
 impl IdCompressor {
@@ -67,11 +69,11 @@ impl IdCompressor {
     /// Creates a new compressor with the supplied session ID.
     /// The ID string should be a valid v4 UUID string.
     pub fn new(session_id_string: String) -> Result<IdCompressor, JsError> {
-        Ok(IdCompressor {
-            compressor: IdCompressorCore::new_with_session_id(
-                SessionId::from_uuid_string(&session_id_string).map_err(into_jserror)?,
-            ),
-        })
+        let mut compressor = IdCompressorCore::new_with_session_id(
+            SessionId::from_uuid_string(&session_id_string).map_err(into_jserror)?,
+        );
+        compressor.set_final_id_ceiling(FINAL_ID_CEILING);
+        Ok(IdCompressor { compressor })
     }
 
     /// Returns the local session ID UUID.
@@ -103,8 +105,13 @@ impl IdCompressor {
 
     /// Generates a new ID.
     /// See [distributed_id_allocator::compressor::IdCompressor] for more.
-    pub fn generate_next_id(&mut self) -> f64 {
-        self.compressor.generate_next_id().id() as f64
+    /// Throws an error if the ID space is exhausted, including when the ID cannot be exactly represented as an f64.
+    pub fn generate_next_id(&mut self) -> Result<f64, JsError> {
+        Ok(self
+            .compressor
+            .try_generate_next_id()
+            .map_err(into_jserror)?
+            .id() as f64)
     }
 
     /// Returns a number token associated with the supplied session UUID string.
@@ -146,6 +153,7 @@ impl IdCompressor {
             .finalize_range(&IdRange {
                 id: SessionId::from_uuid_string(&session_id_str).map_err(into_jserror)?,
                 range: Some((range_base_count as u64, range_len as u64)),
+                capacity_hint: None,
             })
             .map_err(into_jserror)?;
         let stats = self.compressor.get_telemetry_stats();
@@ -162,9 +170,9 @@ impl IdCompressor {
     /// string can be retrieved by calling `get_normalization_error_string`.
     /// See [distributed_id_allocator::compressor::IdCompressor] for more.
     pub fn normalize_to_op_space(&mut self, session_space_id: f64) -> f64 {
-        match &self
-            .compressor
-            .normalize_to_op_space(SessionSpaceId::from_id(session_space_id as i64))
+        match &id_from_f64(session_space_id)
+            .and_then(SessionSpaceId::try_from)
+            .and_then(|session_space_id| self.compressor.normalize_to_op_space(session_space_id))
         {
             Err(_) => NAN,
             Ok(op_space_id) => op_space_id.id() as f64,
@@ -179,10 +187,12 @@ impl IdCompressor {
         // Safe to cast token because the TS layer sends nil token iff passing FinalId and a SessionId it has not tokenized.
         // This can occur when normalizing an ID referenced by a client that has not finalized any IDs,
         // and thus is not yet in the Sessions list.
-        match &self.compressor.normalize_to_session_space_with_token(
-            OpSpaceId::from_id(op_space_id as i64),
-            originator_token as i64,
-        ) {
+        match &id_from_f64(op_space_id)
+            .and_then(OpSpaceId::try_from)
+            .and_then(|op_space_id| {
+                self.compressor
+                    .normalize_to_session_space_with_token(op_space_id, originator_token as i64)
+            }) {
             Err(_) => NAN,
             Ok(session_space_id) => session_space_id.id() as f64,
         }
@@ -194,7 +204,11 @@ impl IdCompressor {
     pub fn decompress(&mut self, id_to_decompress: f64) -> Option<Vec<u8>> {
         let stable_id = self
             .compressor
-            .decompress(SessionSpaceId::from_id(id_to_decompress as i64))
+            .decompress(
+                id_from_f64(id_to_decompress)
+                    .and_then(SessionSpaceId::try_from)
+                    .ok()?,
+            )
             .ok()?;
         Some(stable_id.into())
     }
@@ -222,12 +236,11 @@ impl IdCompressor {
     /// See [distributed_id_allocator::compressor::IdCompressor] for more.
     pub fn deserialize(bytes: &[u8], session_id_string: String) -> Result<IdCompressor, JsError> {
         let session_id = SessionId::from_uuid_string(&session_id_string).map_err(into_jserror)?;
-        Ok(IdCompressor {
-            compressor: IdCompressorCore::deserialize_with_session_id_generator(bytes, || {
-                session_id
-            })
-            .map_err(into_jserror)?,
-        })
+        let mut compressor =
+            IdCompressorCore::deserialize_with_session_id_generator(bytes, || session_id)
+                .map_err(into_jserror)?;
+        compressor.set_final_id_ceiling(FINAL_ID_CEILING);
+        Ok(IdCompressor { compressor })
     }
 
     #[cfg(feature = "debug-format")]
@@ -301,6 +314,16 @@ fn into_jserror(error: impl ErrorString) -> JsError {
     JsError::new(error.to_error_string())
 }
 
+// Casting a non-integral f64 to an i64 truncates (and NaN becomes 0), so such values are rejected rather than silently
+// normalized or decompressed as a different ID.
+fn id_from_f64(id: f64) -> Result<i64, AllocatorError> {
+    if id.is_finite() && id.fract() == 0.0 {
+        Ok(id as i64)
+    } else {
+        Err(AllocatorError::IdOutOfRange)
+    }
+}
+
 #[wasm_bindgen]
 #[cfg(debug_assertions)]
 /// Increments the supplied UUID and returns the result.
@@ -340,7 +363,7 @@ mod tests {
         let mut compressor = IdCompressor::new(String::from(_STABLE_ID_1)).ok().unwrap();
         let mut generated_ids: Vec<f64> = Vec::new();
         for _ in 0..5 {
-            generated_ids.push(compressor.generate_next_id());
+            generated_ids.push(compressor.generate_next_id().ok().unwrap());
         }
         (compressor, generated_ids)
     }
@@ -376,11 +399,25 @@ mod tests {
     fn generate_next_id() {
         let (mut compressor, generated_ids) = initialize_compressor();
         assert_eq!(
-            compressor.generate_next_id(),
+            compressor.generate_next_id().ok().unwrap(),
             generated_ids[generated_ids.len() - 1] - 1.0
         );
     }
 
+    #[test]
+    #[should_panic]
+    fn generate_next_id_exhausted() {
+        let (mut compressor, generated_ids) = initialize_compressor();
+        assert_eq!(
+            compressor.compressor.get_final_id_ceiling(),
+            FINAL_ID_CEILING
+        );
+        compressor
+            .compressor
+            .set_final_id_ceiling(generated_ids.len() as u64 + 1);
+        _ = compressor.generate_next_id();
+    }
+
     #[test]
     #[should_panic]
     fn get_token_invalid_uuid() {
@@ -449,7 +486,7 @@ mod tests {
                 id
             );
         }
-        let new_final = compressor.generate_next_id();
+        let new_final = compressor.generate_next_id().ok().unwrap();
         assert_eq!(compressor.normalize_to_op_space(new_final), new_final);
 
         assert!(compressor
@@ -483,6 +520,19 @@ mod tests {
         _ = compressor.normalize_to_session_space(-3_f64, -1.0);
     }
 
+    #[test]
+    fn non_integral_ids_are_rejected() {
+        let (mut compressor, _) = initialize_compressor();
+        finalize_compressor(&mut compressor);
+        // Final ID 0 exists, so truncating any of these would yield a valid ID.
+        assert_eq!(compressor.normalize_to_op_space(0.0), 0.0);
+        for id in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 0.5, -0.5] {
+            assert!(compressor.normalize_to_op_space(id).is_nan());
+            assert!(compressor.normalize_to_session_space(id, 0.0).is_nan());
+            assert!(compressor.decompress(id).is_none());
+        }
+    }
+
     #[test]
     fn decompress_invalid() {
         let (mut compressor, _) = initialize_compressor();